@@ -1,15 +1,45 @@
 use thiserror::Error;
 
+use crate::parser::incremental::Incomplete;
+
 #[derive(Debug, Error)]
 pub enum ShellError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     #[error("Parse error: {0}")]
     ParseError(String),
+    /// The input ends inside a construct that more input could complete.
+    #[error("Parse error: {0}")]
+    Incomplete(Incomplete),
     #[error("{0}")]
     CommandNotFound(String),
     #[error("Permission denied: {0}")]
     PermissionDenied(String),
     #[error("Internal error: {0}")]
     InternalError(String),
+    /// `set -u` and an unset parameter, named as written after the `$`.
+    #[error("{0}: unbound variable")]
+    Unbound(String),
+}
+
+impl ShellError {
+    /// The text reported for the error. Command-level errors already name
+    /// the command and are printed as they are.
+    pub fn message(&self) -> String {
+        match self {
+            ShellError::CommandNotFound(msg)
+            | ShellError::PermissionDenied(msg)
+            | ShellError::InternalError(msg) => msg.clone(),
+            e => e.to_string(),
+        }
+    }
+
+    /// The exit status of a command that failed with this error.
+    pub fn status(&self) -> i32 {
+        match self {
+            ShellError::CommandNotFound(_) | ShellError::Unbound(_) => 127,
+            ShellError::PermissionDenied(_) => 126,
+            _ => 1,
+        }
+    }
 }
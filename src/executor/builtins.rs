@@ -1,53 +1,731 @@
 use std::{
     env,
-    fs::OpenOptions,
-    io::{self, Write},
-    str,
+    fs::File,
+    io::{self, Read, Write},
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
 };
 
-use crate::{error::ShellError, parser::ast::Command, shell::Shell};
+use crate::{
+    error::ShellError,
+    executor::{
+        expansion::ExpandedCommand,
+        foreground_job,
+        redirection::{apply_redirections, describe, open_redirections},
+    },
+    parser::printer::single_quote,
+    shell::{
+        Shell,
+        hash::{default_path, is_executable_file, search_path, search_path_all},
+        jobs::JobState,
+        signals::{SIGNALS, reset_signals, signal_name, signal_number},
+        traps::Condition,
+    },
+};
+
+const BUILTINS: &[&str] = &[
+    "exit", "echo", "type", "pwd", "cd", "alias", "unalias", "set", "jobs", "fg", "bg", "wait",
+    "kill", "trap", "exec", "hash", "command", "builtin", "ulimit", "umask",
+];
+
+/// Reserved words that `type` reports as keywords.
+const KEYWORDS: &[&str] = &["!", "[[", "]]", "time"];
 
-const BUILTINS: &[&str] = &["exit", "echo", "type", "pwd", "cd"];
+/// Builtins that POSIX calls special: an error in one of them ends a
+/// non-interactive shell in POSIX mode.
+const SPECIAL_BUILTINS: &[&str] = &["exit", "set", "trap", "exec"];
+
+/// Options that `set -o` and `set +o` turn on and off.
+const SHELL_OPTIONS: &[&str] = &[
+    "errexit", "nounset", "pipefail", "posix", "verbose", "xtrace",
+];
+
+/// The single-letter flags of `set` and the options they stand for.
+const SHORT_OPTIONS: &[(char, &str)] = &[
+    ('e', "errexit"),
+    ('u', "nounset"),
+    ('v', "verbose"),
+    ('x', "xtrace"),
+];
 
 pub fn is_builtin(program: &str) -> bool {
     BUILTINS.contains(&program)
 }
 
-pub fn execute_builtin(_shell: &mut Shell, command: &Command) -> Result<i32, ShellError> {
-    match command.program.as_str() {
-        "exit" => execute_exit(&command.arguments),
-        "echo" => execute_echo(command),
-        "type" => execute_type(command),
-        "pwd" => execute_pwd(command),
+pub fn is_special_builtin(program: &str) -> bool {
+    SPECIAL_BUILTINS.contains(&program)
+}
+
+/// Where a builtin reads and writes once its redirections are applied.
+pub struct BuiltinIo {
+    pub stdin: Box<dyn Read>,
+    pub stdout: Box<dyn Write>,
+    pub stderr: Box<dyn Write>,
+}
+
+impl BuiltinIo {
+    /// The shell's own standard input, output and error.
+    pub fn standard() -> Self {
+        BuiltinIo {
+            stdin: Box::new(io::stdin()),
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+        }
+    }
+
+    pub fn open(command: &ExpandedCommand) -> Result<Self, ShellError> {
+        let mut io = BuiltinIo::standard();
+        for (fd, target) in open_redirections(&command.redirections)? {
+            let file = File::from(target);
+            match fd {
+                0 => io.stdin = Box::new(file),
+                1 => io.stdout = Box::new(file),
+                2 => io.stderr = Box::new(file),
+                _ => {}
+            }
+        }
+        Ok(io)
+    }
+}
+
+/// Runs a builtin with its redirections applied. Errors, including a
+/// redirection that cannot be opened, are reported on the builtin's standard
+/// error and give status 1.
+pub fn execute_builtin(shell: &mut Shell, command: &ExpandedCommand) -> Result<i32, ShellError> {
+    // `exec` applies its redirections to the shell itself.
+    let mut io = if command.program == "exec" {
+        BuiltinIo::standard()
+    } else {
+        match BuiltinIo::open(command) {
+            Ok(io) => io,
+            Err(e) => {
+                Shell::report_error(&e);
+                return Ok(builtin_failed(shell, command));
+            }
+        }
+    };
+    let result = match command.program.as_str() {
+        "exec" => execute_exec(shell, command, &mut io),
+        "exit" => execute_exit(shell, &command.arguments, &mut io),
+        "echo" => execute_echo(command, &mut io),
+        "type" => execute_type(shell, command, &mut io),
+        "pwd" => execute_pwd(&mut io),
         "cd" => execute_cd(&command.arguments),
+        "alias" => execute_alias(shell, command, &mut io),
+        "unalias" => execute_unalias(shell, &command.arguments, &mut io),
+        "set" => execute_set(shell, command, &mut io),
+        "jobs" => execute_jobs(shell, &command.arguments, &mut io),
+        "fg" => execute_fg(shell, &command.arguments, &mut io),
+        "bg" => execute_bg(shell, &command.arguments, &mut io),
+        "wait" => execute_wait(shell, &command.arguments, &mut io),
+        "kill" => execute_kill(shell, &command.arguments, &mut io),
+        "trap" => execute_trap(shell, &command.arguments, &mut io),
+        "hash" => execute_hash(shell, &command.arguments, &mut io),
+        "command" => execute_command(shell, &command.arguments, &mut io),
+        "builtin" => Ok(0),
+        "ulimit" => execute_ulimit(&command.arguments, &mut io),
+        "umask" => execute_umask(&command.arguments, &mut io),
         _ => Err(ShellError::CommandNotFound(format!(
             "{}: command not found",
             command.program
         ))),
+    };
+
+    let result = result.or_else(|e| {
+        writeln!(io.stderr, "{}", e.message())?;
+        Ok(builtin_failed(shell, command))
+    });
+    io.stdout.flush()?;
+    io.stderr.flush()?;
+    result
+}
+
+/// The status of a builtin that failed. In POSIX mode an error in a special
+/// builtin ends a non-interactive shell.
+fn builtin_failed(shell: &mut Shell, command: &ExpandedCommand) -> i32 {
+    if shell.posix && !shell.interactive && is_special_builtin(&command.program) {
+        shell.exit(1);
+    }
+    1
+}
+
+/// `exec [-cl] [-a name] [command [args ...]]`: replaces the shell with the
+/// command, or without one makes the redirections permanent. Its errors go
+/// to the standard error its redirections set up.
+fn execute_exec(
+    shell: &mut Shell,
+    command: &ExpandedCommand,
+    io: &mut BuiltinIo,
+) -> Result<i32, ShellError> {
+    let redirections = open_redirections(&command.redirections)?;
+    if let Some((_, fd)) = redirections.iter().rev().find(|(target, _)| *target == 2) {
+        io.stderr = Box::new(File::from(fd.try_clone()?));
+    }
+
+    let mut args = command.arguments.iter();
+    let (mut name, mut clear_env, mut login) = (None, false, false);
+    let program = loop {
+        let Some(arg) = args.next() else {
+            break None;
+        };
+        match arg.as_str() {
+            "-a" => {
+                name = Some(args.next().ok_or_else(|| {
+                    ShellError::InternalError("exec: -a: option requires an argument".to_string())
+                })?);
+            }
+            "-c" => clear_env = true,
+            "-l" => login = true,
+            "--" => break args.next(),
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(ShellError::InternalError(format!(
+                    "exec: {}: invalid option\nexec: usage: exec [-cl] [-a name] [command [argument ...]] [redirection ...]",
+                    arg
+                )));
+            }
+            _ => break Some(arg),
+        }
+    };
+    let Some(program) = program else {
+        io::stdout().flush()?;
+        apply_redirections(&redirections)?;
+        return Ok(0);
+    };
+
+    let Some(path) = shell.resolve_command(program) else {
+        writeln!(io.stderr, "exec: {}: not found", program)?;
+        if !shell.interactive {
+            shell.exit(127);
+        }
+        return Ok(127);
+    };
+    let mut process = std::process::Command::new(path);
+    process.args(args);
+    let name = name.unwrap_or(program);
+    if login {
+        process.arg0(format!("-{}", name));
+    } else {
+        process.arg0(name);
+    }
+    if clear_env {
+        process.env_clear();
+    } else {
+        process.envs(shell.exported_vars());
+    }
+    let restored = shell.restored_signals();
+    unsafe {
+        process.pre_exec(move || {
+            reset_signals(&restored);
+            apply_redirections(&redirections)
+        });
+    }
+
+    io::stdout().flush()?;
+    let error = process.exec();
+    let (message, status) = match error.kind() {
+        io::ErrorKind::NotFound => (format!("exec: {}: not found", program), 127),
+        io::ErrorKind::PermissionDenied => (format!("exec: {}: Permission denied", program), 126),
+        _ => (format!("exec: {}: {}", program, describe(&error)), 126),
+    };
+    writeln!(io.stderr, "{}", message)?;
+    // A shell that cannot exec its command has nothing left to run.
+    if !shell.interactive {
+        shell.exit(status);
+    }
+    Ok(status)
+}
+
+/// `hash [-r] [-d] [-p path] [name ...]`: lists the remembered locations of
+/// commands, or finds, forgets or sets those of the names given.
+fn execute_hash(shell: &mut Shell, args: &[String], io: &mut BuiltinIo) -> Result<i32, ShellError> {
+    let mut args = args.iter();
+    let (mut reset, mut delete, mut path) = (false, false, None);
+    let mut names = vec![];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-r" => {
+                shell.hash.clear();
+                reset = true;
+            }
+            "-d" => delete = true,
+            "-p" => {
+                path = Some(args.next().ok_or_else(|| {
+                    ShellError::InternalError("hash: -p: option requires an argument".to_string())
+                })?);
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(ShellError::InternalError(format!(
+                    "hash: {}: invalid option\nhash: usage: hash [-r] [-p pathname] [-d] [name ...]",
+                    arg
+                )));
+            }
+            _ => names.push(arg),
+        }
+    }
+
+    if names.is_empty() {
+        if reset || delete || path.is_some() {
+            return Ok(0);
+        }
+        let mut entries = shell.hash.entries().peekable();
+        if entries.peek().is_none() {
+            writeln!(io.stdout, "hash: hash table empty")?;
+            return Ok(0);
+        }
+        writeln!(io.stdout, "hits\tcommand")?;
+        for (_, entry) in entries {
+            writeln!(io.stdout, "{:4}\t{}", entry.hits, entry.path.display())?;
+        }
+        return Ok(0);
+    }
+
+    let search = shell.get_var("PATH").unwrap_or_default();
+    let mut status = 0;
+    for name in names {
+        let found = if let Some(path) = path {
+            shell.hash.insert(name, path.into(), &search);
+            true
+        } else if delete {
+            shell.hash.remove(name)
+        } else {
+            is_builtin(name) || shell.hash.find(name, &search).is_some()
+        };
+        if !found {
+            writeln!(io.stderr, "hash: {}: not found", name)?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+/// What `getrlimit` takes a resource as: glibc declares it as an enum of its
+/// own, other C libraries as an `int`.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type Resource = libc::c_int;
+
+/// A resource `ulimit` controls: its flag, `setrlimit` resource, description
+/// and unit, and how many bytes or units the shown value counts as one.
+struct Limit {
+    flag: char,
+    resource: Resource,
+    description: &'static str,
+    unit: Option<&'static str>,
+    factor: libc::rlim_t,
+}
+
+const LIMITS: &[Limit] = &[
+    Limit {
+        flag: 'R',
+        resource: libc::RLIMIT_RTTIME,
+        description: "real-time non-blocking time",
+        unit: Some("microseconds"),
+        factor: 1,
+    },
+    Limit {
+        flag: 'c',
+        resource: libc::RLIMIT_CORE,
+        description: "core file size",
+        unit: Some("blocks"),
+        factor: 512,
+    },
+    Limit {
+        flag: 'd',
+        resource: libc::RLIMIT_DATA,
+        description: "data seg size",
+        unit: Some("kbytes"),
+        factor: 1024,
+    },
+    Limit {
+        flag: 'e',
+        resource: libc::RLIMIT_NICE,
+        description: "scheduling priority",
+        unit: None,
+        factor: 1,
+    },
+    Limit {
+        flag: 'f',
+        resource: libc::RLIMIT_FSIZE,
+        description: "file size",
+        unit: Some("blocks"),
+        factor: 512,
+    },
+    Limit {
+        flag: 'i',
+        resource: libc::RLIMIT_SIGPENDING,
+        description: "pending signals",
+        unit: None,
+        factor: 1,
+    },
+    Limit {
+        flag: 'l',
+        resource: libc::RLIMIT_MEMLOCK,
+        description: "max locked memory",
+        unit: Some("kbytes"),
+        factor: 1024,
+    },
+    Limit {
+        flag: 'm',
+        resource: libc::RLIMIT_RSS,
+        description: "max memory size",
+        unit: Some("kbytes"),
+        factor: 1024,
+    },
+    Limit {
+        flag: 'n',
+        resource: libc::RLIMIT_NOFILE,
+        description: "open files",
+        unit: None,
+        factor: 1,
+    },
+    Limit {
+        flag: 'q',
+        resource: libc::RLIMIT_MSGQUEUE,
+        description: "POSIX message queues",
+        unit: Some("bytes"),
+        factor: 1,
+    },
+    Limit {
+        flag: 'r',
+        resource: libc::RLIMIT_RTPRIO,
+        description: "real-time priority",
+        unit: None,
+        factor: 1,
+    },
+    Limit {
+        flag: 's',
+        resource: libc::RLIMIT_STACK,
+        description: "stack size",
+        unit: Some("kbytes"),
+        factor: 1024,
+    },
+    Limit {
+        flag: 't',
+        resource: libc::RLIMIT_CPU,
+        description: "cpu time",
+        unit: Some("seconds"),
+        factor: 1,
+    },
+    Limit {
+        flag: 'u',
+        resource: libc::RLIMIT_NPROC,
+        description: "max user processes",
+        unit: None,
+        factor: 1,
+    },
+    Limit {
+        flag: 'v',
+        resource: libc::RLIMIT_AS,
+        description: "virtual memory",
+        unit: Some("kbytes"),
+        factor: 1024,
+    },
+    Limit {
+        flag: 'x',
+        resource: libc::RLIMIT_LOCKS,
+        description: "file locks",
+        unit: None,
+        factor: 1,
+    },
+];
+
+impl Limit {
+    fn get(&self) -> Result<libc::rlimit, ShellError> {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(self.resource, &mut limit) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(limit)
+    }
+
+    fn show(&self, value: libc::rlim_t) -> String {
+        if value == libc::RLIM_INFINITY {
+            "unlimited".to_string()
+        } else {
+            (value / self.factor).to_string()
+        }
+    }
+
+    /// Parses a new value for the limit, given in its unit, as `unlimited`,
+    /// or as `hard` or `soft` for the `current` value of either.
+    fn parse(&self, value: &str, current: &libc::rlimit) -> Result<libc::rlim_t, ShellError> {
+        match value {
+            "unlimited" => Ok(libc::RLIM_INFINITY),
+            "hard" => Ok(current.rlim_max),
+            "soft" => Ok(current.rlim_cur),
+            _ => value
+                .parse::<libc::rlim_t>()
+                .ok()
+                .and_then(|number| number.checked_mul(self.factor))
+                .ok_or_else(|| {
+                    ShellError::InternalError(format!("ulimit: {}: invalid number", value))
+                }),
+        }
+    }
+
+    /// The line `ulimit -a` prints for the limit, laid out as bash does.
+    fn describe(&self, value: libc::rlim_t) -> String {
+        let unit = match self.unit {
+            Some(unit) => format!("({}, -{}) ", unit, self.flag),
+            None => format!("(-{}) ", self.flag),
+        };
+        format!("{:<20} {:>20}{}", self.description, unit, self.show(value))
+    }
+}
+
+/// `ulimit [-SHa] [-Rcdefilmnqrstuvx] [limit]`: shows or sets the shell's
+/// resource limits, which every command it starts inherits. Setting a limit
+/// without `-S` or `-H` sets both. The default resource is the file size.
+fn execute_ulimit(args: &[String], io: &mut BuiltinIo) -> Result<i32, ShellError> {
+    let (mut soft, mut hard, mut all) = (false, false, false);
+    let mut limits: Vec<&Limit> = vec![];
+    let mut value = None;
+    for arg in args {
+        match arg.strip_prefix('-') {
+            Some(flags) if value.is_none() && !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'S' => soft = true,
+                        'H' => hard = true,
+                        'a' => all = true,
+                        _ => match LIMITS.iter().find(|limit| limit.flag == flag) {
+                            Some(limit) => limits.push(limit),
+                            None => {
+                                return Err(ShellError::InternalError(format!(
+                                    "ulimit: -{}: invalid option",
+                                    flag
+                                )));
+                            }
+                        },
+                    }
+                }
+            }
+            _ if value.is_none() => value = Some(arg),
+            _ => {
+                return Err(ShellError::InternalError(
+                    "ulimit: too many arguments".to_string(),
+                ));
+            }
+        }
+    }
+
+    let show_hard = hard && !soft;
+    if all {
+        for limit in LIMITS {
+            let current = limit.get()?;
+            let shown = if show_hard {
+                current.rlim_max
+            } else {
+                current.rlim_cur
+            };
+            writeln!(io.stdout, "{}", limit.describe(shown))?;
+        }
+        return Ok(0);
+    }
+    if limits.is_empty() {
+        limits.push(LIMITS.iter().find(|limit| limit.flag == 'f').unwrap());
+    }
+
+    let Some(value) = value else {
+        for limit in &limits {
+            let current = limit.get()?;
+            let shown = if show_hard {
+                current.rlim_max
+            } else {
+                current.rlim_cur
+            };
+            if limits.len() > 1 {
+                writeln!(io.stdout, "{}", limit.describe(shown))?;
+            } else {
+                writeln!(io.stdout, "{}", limit.show(shown))?;
+            }
+        }
+        return Ok(0);
+    };
+
+    let mut status = 0;
+    for limit in limits {
+        let mut new = limit.get()?;
+        let requested = limit.parse(value, &new)?;
+        if soft || !hard {
+            new.rlim_cur = requested;
+        }
+        if hard || !soft {
+            new.rlim_max = requested;
+        }
+        if unsafe { libc::setrlimit(limit.resource, &new) } == -1 {
+            let error = io::Error::last_os_error();
+            writeln!(
+                io.stderr,
+                "ulimit: {}: cannot modify limit: {}",
+                limit.description,
+                describe(&error)
+            )?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+/// `umask [-p] [-S] [mode]`: shows or sets the shell's file creation mask,
+/// which files it creates for redirections and every command it starts
+/// inherit. `mode` is octal or symbolic, like `u=rwx,g=rx,o=`.
+fn execute_umask(args: &[String], io: &mut BuiltinIo) -> Result<i32, ShellError> {
+    let (mut symbolic, mut reusable) = (false, false);
+    let mut operands = args.iter();
+    let mode = loop {
+        match operands.next().map(String::as_str) {
+            Some("-S") => symbolic = true,
+            Some("-p") => reusable = true,
+            Some("--") => break operands.next().map(String::as_str),
+            Some(flags) if flags.starts_with('-') && flags.len() > 1 => {
+                for flag in flags[1..].chars() {
+                    match flag {
+                        'S' => symbolic = true,
+                        'p' => reusable = true,
+                        _ => {
+                            return Err(ShellError::InternalError(format!(
+                                "umask: -{}: invalid option",
+                                flag
+                            )));
+                        }
+                    }
+                }
+            }
+            mode => break mode,
+        }
+    };
+
+    let current = unsafe { libc::umask(0) };
+    unsafe { libc::umask(current) };
+    let Some(mode) = mode else {
+        write_umask(current, symbolic, reusable, io)?;
+        return Ok(0);
+    };
+
+    let mask = parse_umask(mode, current)?;
+    unsafe { libc::umask(mask) };
+    if symbolic {
+        write_umask(mask, symbolic, reusable, io)?;
     }
+    Ok(0)
+}
+
+fn write_umask(
+    mask: libc::mode_t,
+    symbolic: bool,
+    reusable: bool,
+    io: &mut BuiltinIo,
+) -> Result<(), ShellError> {
+    let value = if symbolic {
+        let allowed = !mask & 0o777;
+        let class = |shift: u32| {
+            let bits = allowed >> shift;
+            ["r", "w", "x"]
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| bits & (0o4 >> index) != 0)
+                .map(|(_, letter)| *letter)
+                .collect::<String>()
+        };
+        format!("u={},g={},o={}", class(6), class(3), class(0))
+    } else {
+        format!("{:04o}", mask)
+    };
+    match (reusable, symbolic) {
+        (true, true) => writeln!(io.stdout, "umask -S {}", value)?,
+        (true, false) => writeln!(io.stdout, "umask {}", value)?,
+        (false, _) => writeln!(io.stdout, "{}", value)?,
+    }
+    Ok(())
 }
 
-fn get_command_writer(command: &Command) -> Result<Box<dyn Write>, ShellError> {
-    let mut writer: Box<dyn Write> = Box::new(io::stdout());
-    for redir in &command.outputs {
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .append(redir.append)
-            .truncate(!redir.append)
-            .open(&redir.path)?;
+/// The mask an octal or symbolic `mode` sets, given the `current` one.
+fn parse_umask(mode: &str, current: libc::mode_t) -> Result<libc::mode_t, ShellError> {
+    if mode.starts_with(|c: char| c.is_ascii_digit()) {
+        libc::mode_t::from_str_radix(mode, 8)
+            .ok()
+            .filter(|&mask| mask <= 0o777)
+            .ok_or_else(|| {
+                ShellError::InternalError(format!("umask: {}: octal number out of range", mode))
+            })
+    } else {
+        symbolic_umask(mode, current)
+    }
+}
 
-        if redir.fd == 1 {
-            writer = Box::new(file);
+/// Applies a symbolic mode such as `u=rwx,g-w` to the permissions `mask`
+/// leaves, and returns the mask that leaves the new ones.
+fn symbolic_umask(mode: &str, mask: libc::mode_t) -> Result<libc::mode_t, ShellError> {
+    let mut allowed = !mask & 0o777;
+    for clause in mode.split(',') {
+        let mut chars = clause.chars().peekable();
+        let mut who = 0;
+        while let Some(&c) = chars.peek() {
+            who |= match c {
+                'u' => 0o700,
+                'g' => 0o070,
+                'o' => 0o007,
+                'a' => 0o777,
+                _ => break,
+            };
+            chars.next();
+        }
+        if who == 0 {
+            who = 0o777;
+        }
+        loop {
+            let operator = match chars.next() {
+                Some(c @ ('+' | '-' | '=')) => c,
+                Some(c) => {
+                    return Err(ShellError::InternalError(format!(
+                        "umask: `{}': invalid symbolic mode operator",
+                        c
+                    )));
+                }
+                None if clause.is_empty() => break,
+                None => {
+                    return Err(ShellError::InternalError(
+                        "umask: missing symbolic mode operator".to_string(),
+                    ));
+                }
+            };
+            let mut perms = 0;
+            while let Some(&c) = chars.peek() {
+                perms |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    '+' | '-' | '=' => break,
+                    _ => {
+                        return Err(ShellError::InternalError(format!(
+                            "umask: `{}': invalid symbolic mode character",
+                            c
+                        )));
+                    }
+                };
+                chars.next();
+            }
+            match operator {
+                '+' => allowed |= perms & who,
+                '-' => allowed &= !(perms & who),
+                _ => allowed = (allowed & !who) | (perms & who),
+            }
+            if chars.peek().is_none() {
+                break;
+            }
         }
     }
-    Ok(writer)
+    Ok(!allowed & 0o777)
 }
 
-fn execute_pwd(command: &Command) -> Result<i32, ShellError> {
+fn execute_pwd(io: &mut BuiltinIo) -> Result<i32, ShellError> {
     let current_path = env::current_dir()?;
-    let mut writer = get_command_writer(command)?;
-    writeln!(writer, "{}", current_path.display())?;
+    writeln!(io.stdout, "{}", current_path.display())?;
     Ok(0)
 }
 
@@ -99,56 +777,1086 @@ fn execute_cd(args: &[String]) -> Result<i32, ShellError> {
     }
 }
 
-fn execute_exit(args: &[String]) -> Result<i32, ShellError> {
-    let exit_code = args
-        .first()
-        .and_then(|s| s.parse::<i32>().ok())
-        .unwrap_or(0);
-
-    std::process::exit(exit_code);
+/// `exit [n]`: exits with `n`, or with `$?`, after running the EXIT trap.
+fn execute_exit(shell: &mut Shell, args: &[String], io: &mut BuiltinIo) -> Result<i32, ShellError> {
+    let status = match args.first() {
+        None => shell.last_status,
+        Some(arg) => arg.parse::<i32>().unwrap_or_else(|_| {
+            let _ = writeln!(io.stderr, "exit: {}: numeric argument required", arg);
+            2
+        }),
+    };
+    io.stdout.flush()?;
+    shell.exit(status);
 }
 
-fn execute_echo(command: &Command) -> Result<i32, ShellError> {
-    let mut writer = get_command_writer(command)?;
+fn execute_echo(command: &ExpandedCommand, io: &mut BuiltinIo) -> Result<i32, ShellError> {
     let output = command.arguments.join(" ");
-    writeln!(writer, "{}", output)?;
+    writeln!(io.stdout, "{}", output)?;
     Ok(0)
 }
 
-fn execute_type(command: &Command) -> Result<i32, ShellError> {
-    let args = &command.arguments;
+fn execute_alias(
+    shell: &mut Shell,
+    command: &ExpandedCommand,
+    io: &mut BuiltinIo,
+) -> Result<i32, ShellError> {
+    let args: Vec<&String> = command
+        .arguments
+        .iter()
+        .filter(|arg| arg.as_str() != "-p")
+        .collect();
+
+    if args.is_empty() {
+        for (name, value) in &shell.aliases {
+            write_alias(&mut io.stdout, shell, name, value)?;
+        }
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for arg in args {
+        if let Some((name, value)) = arg.split_once('=') {
+            if name.is_empty() || name.contains(['/', '$', '`', '\\', '\'', '"']) {
+                writeln!(io.stderr, "alias: `{}': invalid alias name", name)?;
+                status = 1;
+                continue;
+            }
+            shell.aliases.insert(name.to_string(), value.to_string());
+        } else if let Some(value) = shell.aliases.get(arg.as_str()) {
+            write_alias(&mut io.stdout, shell, arg, value)?;
+        } else {
+            writeln!(io.stderr, "alias: {}: not found", arg)?;
+            status = 1;
+        }
+    }
+
+    Ok(status)
+}
+
+/// Prints an alias so it can be read back in: `alias name='value'`, or just
+/// `name='value'` in POSIX mode.
+fn write_alias(
+    writer: &mut dyn Write,
+    shell: &Shell,
+    name: &str,
+    value: &str,
+) -> Result<(), ShellError> {
+    let prefix = if shell.posix { "" } else { "alias " };
+    writeln!(writer, "{}{}={}", prefix, name, single_quote(value))?;
+    Ok(())
+}
+
+fn execute_unalias(
+    shell: &mut Shell,
+    args: &[String],
+    io: &mut BuiltinIo,
+) -> Result<i32, ShellError> {
     if args.is_empty() {
+        return Err(ShellError::InternalError(
+            "unalias: usage: unalias [-a] name [name ...]".to_string(),
+        ));
+    }
+
+    if args.iter().any(|arg| arg == "-a") {
+        shell.aliases.clear();
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for name in args {
+        if shell.aliases.remove(name).is_none() {
+            writeln!(io.stderr, "unalias: {}: not found", name)?;
+            status = 1;
+        }
+    }
+
+    Ok(status)
+}
+
+/// What a command name refers to, in the order the shell looks.
+enum CommandKind {
+    Alias(String),
+    Keyword,
+    Builtin,
+    File { path: PathBuf, hashed: bool },
+}
+
+impl CommandKind {
+    /// The word `type -t` prints.
+    fn type_word(&self) -> &'static str {
+        match self {
+            CommandKind::Alias(_) => "alias",
+            CommandKind::Keyword => "keyword",
+            CommandKind::Builtin => "builtin",
+            CommandKind::File { .. } => "file",
+        }
+    }
+
+    /// The sentence `type` and `command -V` print.
+    fn describe(&self, shell: &Shell, name: &str) -> String {
+        match self {
+            CommandKind::Alias(value) => format!("{} is aliased to `{}'", name, value),
+            CommandKind::Keyword => format!("{} is a shell keyword", name),
+            CommandKind::Builtin if shell.posix && is_special_builtin(name) => {
+                format!("{} is a special shell builtin", name)
+            }
+            CommandKind::Builtin => format!("{} is a shell builtin", name),
+            CommandKind::File { path, hashed: true } => {
+                format!("{} is hashed ({})", name, path.display())
+            }
+            CommandKind::File { path, .. } => format!("{} is {}", name, path.display()),
+        }
+    }
+}
+
+/// Finds what `name` runs as: the first match, or with `all` every alias,
+/// keyword, builtin and file on `PATH`. With `files_only` only files count.
+/// The shell has no functions, so none are ever found.
+fn command_kinds(shell: &Shell, name: &str, all: bool, files_only: bool) -> Vec<CommandKind> {
+    let mut kinds = vec![];
+    if !files_only {
+        if let Some(value) = shell.aliases.get(name) {
+            kinds.push(CommandKind::Alias(value.clone()));
+        }
+        if KEYWORDS.contains(&name) {
+            kinds.push(CommandKind::Keyword);
+        }
+        if is_builtin(name) {
+            kinds.push(CommandKind::Builtin);
+        }
+    }
+
+    let search = shell.get_var("PATH").unwrap_or_default();
+    if name.contains('/') {
+        if is_executable_file(Path::new(name)) {
+            kinds.push(CommandKind::File {
+                path: PathBuf::from(name),
+                hashed: false,
+            });
+        }
+    } else if all {
+        kinds.extend(
+            search_path_all(name, &search)
+                .into_iter()
+                .map(|path| CommandKind::File {
+                    path,
+                    hashed: false,
+                }),
+        );
+    } else if let Some(entry) = shell
+        .hash
+        .get(name)
+        .filter(|entry| is_executable_file(&entry.path))
+    {
+        kinds.push(CommandKind::File {
+            path: entry.path.clone(),
+            hashed: true,
+        });
+    } else if let Some(path) = search_path(name, &search) {
+        kinds.push(CommandKind::File {
+            path,
+            hashed: false,
+        });
+    }
+
+    if !all {
+        kinds.truncate(1);
+    }
+    kinds
+}
+
+/// `type [-afptP] name ...`: describes what each name runs as. `-t` prints
+/// only the kind, `-p` the file if it is one and `-P` searches `PATH` only.
+/// `-f` skips functions, which the shell does not have.
+fn execute_type(
+    shell: &Shell,
+    command: &ExpandedCommand,
+    io: &mut BuiltinIo,
+) -> Result<i32, ShellError> {
+    let (mut all, mut type_only, mut path_only, mut force_path) = (false, false, false, false);
+    let mut names = vec![];
+    for arg in &command.arguments {
+        match arg.strip_prefix('-') {
+            Some(flags) if names.is_empty() && !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'a' => all = true,
+                        't' => type_only = true,
+                        'p' => path_only = true,
+                        'P' => force_path = true,
+                        'f' => {}
+                        _ => {
+                            return Err(ShellError::InternalError(format!(
+                                "type: -{}: invalid option\ntype: usage: type [-afptP] name [name ...]",
+                                flag
+                            )));
+                        }
+                    }
+                }
+            }
+            _ => names.push(arg),
+        }
+    }
+    if names.is_empty() {
         return Err(ShellError::InternalError(
             "need at least one argument".to_string(),
         ));
     }
 
-    let program = &args[0];
-    let mut writer = get_command_writer(command)?;
+    let mut status = 0;
+    for name in names {
+        let kinds = command_kinds(shell, name, all, force_path);
+        if kinds.is_empty() {
+            if !(type_only || path_only || force_path) {
+                writeln!(io.stderr, "{}: not found", name)?;
+            }
+            status = 1;
+            continue;
+        }
+        for kind in kinds {
+            if type_only {
+                writeln!(io.stdout, "{}", kind.type_word())?;
+            } else if path_only || force_path {
+                if let CommandKind::File { path, .. } = kind {
+                    writeln!(io.stdout, "{}", path.display())?;
+                }
+            } else {
+                writeln!(io.stdout, "{}", kind.describe(shell, name))?;
+            }
+        }
+    }
+    Ok(status)
+}
 
-    if is_builtin(program) {
-        writeln!(writer, "{} is a shell builtin", program)?;
-    } else {
-        let output = std::process::Command::new("which")
-            .arg(program)
-            .output()
-            .map_err(|_| ShellError::CommandNotFound(format!("{}: not found", program)))?;
+/// Strips `command` and `builtin` off a command that runs another one, so it
+/// runs as if typed without them: `builtin` only allows a builtin, and
+/// `command -p` finds a file on the default `PATH`. The forms that only look
+/// names up, those without a name and those with an invalid option are left
+/// to run as builtins.
+pub fn unwrap_prefixes(command: &mut ExpandedCommand) -> Result<(), ShellError> {
+    loop {
+        match command.program.as_str() {
+            "command" => {
+                let options = command
+                    .arguments
+                    .iter()
+                    .take_while(|arg| arg.starts_with('-') && arg.len() > 1)
+                    .count();
+                let flags: String = command.arguments[..options]
+                    .iter()
+                    .filter(|arg| arg.as_str() != "--")
+                    .flat_map(|arg| arg.chars().skip(1))
+                    .collect();
+                if flags.contains(|flag| !matches!(flag, 'p' | 'v' | 'V'))
+                    || flags.contains(['v', 'V'])
+                    || command.arguments.len() == options
+                {
+                    return Ok(());
+                }
+                command.arguments.drain(..options);
+                command.program = command.arguments.remove(0);
+                if flags.contains('p')
+                    && !is_builtin(&command.program)
+                    && !command.program.contains('/')
+                    && let Some(path) = search_path(&command.program, &default_path())
+                {
+                    command.program = path.to_string_lossy().into_owned();
+                }
+            }
+            "builtin" => {
+                if command.arguments.is_empty() {
+                    return Ok(());
+                }
+                let name = command.arguments.remove(0);
+                if !is_builtin(&name) {
+                    return Err(ShellError::InternalError(format!(
+                        "builtin: {}: not a shell builtin",
+                        name
+                    )));
+                }
+                command.program = name;
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// `command -v name ...` prints how each name would be run, and
+/// `command -V name ...` describes it like `type`. Other forms are handled
+/// by `unwrap_prefixes` before the command runs.
+fn execute_command(shell: &Shell, args: &[String], io: &mut BuiltinIo) -> Result<i32, ShellError> {
+    let (mut short, mut verbose) = (false, false);
+    let mut names = vec![];
+    for arg in args {
+        match arg.strip_prefix('-') {
+            Some(flags) if names.is_empty() && !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'v' => short = true,
+                        'V' => verbose = true,
+                        'p' | '-' => {}
+                        _ => {
+                            writeln!(io.stderr, "command: -{}: invalid option", flag)?;
+                            writeln!(
+                                io.stderr,
+                                "command: usage: command [-pVv] command [arg ...]"
+                            )?;
+                            return Ok(2);
+                        }
+                    }
+                }
+            }
+            _ => names.push(arg),
+        }
+    }
+    if !short && !verbose {
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for name in names {
+        let Some(kind) = command_kinds(shell, name, false, false).pop() else {
+            if verbose {
+                writeln!(io.stderr, "command: {}: not found", name)?;
+            }
+            status = 1;
+            continue;
+        };
+        if verbose {
+            writeln!(io.stdout, "{}", kind.describe(shell, name))?;
+            continue;
+        }
+        match kind {
+            CommandKind::Alias(value) => write_alias(&mut io.stdout, shell, name, &value)?,
+            CommandKind::Keyword | CommandKind::Builtin => writeln!(io.stdout, "{}", name)?,
+            CommandKind::File { path, .. } => writeln!(io.stdout, "{}", path.display())?,
+        }
+    }
+    Ok(status)
+}
+
+/// `set [-euvx] [+euvx] [-o name] [+o name] [--] [arg ...]`, with the flags
+/// combinable as in `set -euo pipefail`. The first argument that is not an
+/// option, or any after `--`, replace the positional parameters.
+fn execute_set(
+    shell: &mut Shell,
+    command: &ExpandedCommand,
+    io: &mut BuiltinIo,
+) -> Result<i32, ShellError> {
+    let mut args = command.arguments.iter();
+
+    while let Some(arg) = args.next() {
+        let (enable, flags) = match arg.split_at_checked(1) {
+            Some(("-", "-")) => {
+                shell.positional_params = args.cloned().collect();
+                break;
+            }
+            Some(("-", flags)) if !flags.is_empty() => (true, flags),
+            Some(("+", flags)) if !flags.is_empty() => (false, flags),
+            _ => {
+                shell.positional_params = std::iter::once(arg).chain(args).cloned().collect();
+                break;
+            }
+        };
 
-        if !output.status.success() || output.stdout.is_empty() {
-            return Err(ShellError::CommandNotFound(format!(
-                "{}: not found",
-                program
-            )));
+        for flag in flags.chars() {
+            let name = if flag == 'o' {
+                let Some(name) = args.next() else {
+                    // Without a name, `-o` lists the options and `+o` prints
+                    // the commands that would restore them.
+                    for name in SHELL_OPTIONS {
+                        let on = *shell_option(shell, name).unwrap();
+                        if enable {
+                            writeln!(io.stdout, "{:<15}\t{}", name, if on { "on" } else { "off" })?;
+                        } else {
+                            writeln!(io.stdout, "set {}o {}", if on { '-' } else { '+' }, name)?;
+                        }
+                    }
+                    continue;
+                };
+                name.as_str()
+            } else {
+                SHORT_OPTIONS
+                    .iter()
+                    .find(|(short, _)| *short == flag)
+                    .map(|(_, name)| *name)
+                    .ok_or_else(|| {
+                        ShellError::InternalError(format!(
+                            "set: {}{}: invalid option",
+                            &arg[..1],
+                            flag
+                        ))
+                    })?
+            };
+
+            match shell_option(shell, name) {
+                Some(option) => *option = enable,
+                None => {
+                    return Err(ShellError::InternalError(format!(
+                        "set: {}: invalid option name",
+                        name
+                    )));
+                }
+            }
         }
+    }
 
-        let stdout_str = str::from_utf8(&output.stdout)
-            .map_err(|_| ShellError::InternalError("Not valid UTF-8".to_string()))?;
+    Ok(0)
+}
 
-        write!(writer, "{} is {}", program, stdout_str)?;
-        if !stdout_str.ends_with('\n') {
-            writeln!(writer)?;
+fn shell_option<'a>(shell: &'a mut Shell, name: &str) -> Option<&'a mut bool> {
+    match name {
+        "errexit" => Some(&mut shell.errexit),
+        "nounset" => Some(&mut shell.nounset),
+        "pipefail" => Some(&mut shell.pipefail),
+        "verbose" => Some(&mut shell.verbose),
+        "xtrace" => Some(&mut shell.xtrace),
+        "posix" => Some(&mut shell.posix),
+        _ => None,
+    }
+}
+
+fn execute_jobs(shell: &mut Shell, args: &[String], io: &mut BuiltinIo) -> Result<i32, ShellError> {
+    let (mut long, mut pids_only) = (false, false);
+    let mut specs = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "-l" => long = true,
+            "-p" => pids_only = true,
+            _ if arg.starts_with('-') => {
+                return Err(ShellError::InternalError(format!(
+                    "jobs: {}: invalid option",
+                    arg
+                )));
+            }
+            _ => specs.push(arg),
         }
+    }
+
+    shell.jobs.update();
+    let ids = if specs.is_empty() {
+        shell.jobs.jobs().iter().map(|job| job.id).collect()
+    } else {
+        let mut ids = vec![];
+        for spec in specs {
+            ids.push(
+                shell
+                    .jobs
+                    .resolve(spec)
+                    .map_err(|e| ShellError::InternalError(format!("jobs: {}", e)))?,
+            );
+        }
+        ids
     };
 
+    for id in ids {
+        let job = shell.jobs.get(id).unwrap();
+        if pids_only {
+            writeln!(io.stdout, "{}", job.pgid)?;
+        } else {
+            writeln!(io.stdout, "{}", shell.jobs.format(job, long))?;
+        }
+    }
+    // Finished jobs are listed once, then forgotten.
+    shell.jobs.take_finished();
     Ok(0)
 }
+
+/// Resolves the job operand of `fg` or `bg`, the current job by default.
+fn job_operand(shell: &Shell, name: &str, args: &[String]) -> Result<usize, ShellError> {
+    match args.first() {
+        Some(spec) => shell.jobs.resolve(spec),
+        None => shell
+            .jobs
+            .resolve("%+")
+            .map_err(|_| "current: no such job".to_string()),
+    }
+    .map_err(|e| ShellError::InternalError(format!("{}: {}", name, e)))
+}
+
+fn execute_fg(shell: &mut Shell, args: &[String], io: &mut BuiltinIo) -> Result<i32, ShellError> {
+    let id = job_operand(shell, "fg", args)?;
+    writeln!(io.stdout, "{}", shell.jobs.get(id).unwrap().command)?;
+    io.stdout.flush()?;
+    let statuses = foreground_job(shell, id);
+    Ok(statuses.last().copied().unwrap_or(0))
+}
+
+fn execute_bg(shell: &mut Shell, args: &[String], io: &mut BuiltinIo) -> Result<i32, ShellError> {
+    let id = job_operand(shell, "bg", args)?;
+    let job = shell.jobs.get_mut(id).unwrap();
+    if !matches!(job.state, JobState::Stopped(_)) {
+        writeln!(io.stderr, "bg: job {} already in background", id)?;
+        return Ok(0);
+    }
+    unsafe { libc::killpg(job.pgid, libc::SIGCONT) };
+    job.state = JobState::Running;
+    shell.jobs.make_current(id);
+    let job = shell.jobs.get(id).unwrap();
+    writeln!(io.stdout, "[{}]+ {} &", id, job.command)?;
+    Ok(0)
+}
+
+/// `wait [id ...]`: waits for the given jobs or process ids, or for every job,
+/// and returns the status of the last one.
+fn execute_wait(shell: &mut Shell, args: &[String], io: &mut BuiltinIo) -> Result<i32, ShellError> {
+    if args.is_empty() {
+        let ids: Vec<usize> = shell.jobs.jobs().iter().map(|job| job.id).collect();
+        for id in ids {
+            if let JobState::Done(_) = shell.jobs.wait(id) {
+                shell.jobs.remove(id);
+            }
+        }
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for arg in args {
+        let id = if arg.starts_with('%') {
+            shell.jobs.resolve(arg).map_err(|e| format!("wait: {}", e))
+        } else {
+            match arg.parse() {
+                Ok(pid) => shell
+                    .jobs
+                    .find_pid(pid)
+                    .ok_or_else(|| format!("wait: pid {} is not a child of this shell", pid)),
+                Err(_) => Err(format!("wait: `{}': not a pid or valid job spec", arg)),
+            }
+        };
+        status = match id {
+            Ok(id) => match shell.jobs.wait(id) {
+                JobState::Done(status) => {
+                    shell.jobs.remove(id);
+                    status
+                }
+                JobState::Stopped(signal) => 128 + signal,
+                JobState::Running => 0,
+            },
+            Err(message) => {
+                writeln!(io.stderr, "{}", message)?;
+                127
+            }
+        };
+    }
+    Ok(status)
+}
+
+/// `kill [-s sig | -n num | -sig] pid | %job ...` and `kill -l [status]`.
+fn execute_kill(shell: &mut Shell, args: &[String], io: &mut BuiltinIo) -> Result<i32, ShellError> {
+    let usage = || {
+        ShellError::InternalError(
+            "kill: usage: kill [-s sigspec | -n signum | -sigspec] pid | jobspec ... or kill -l [sigspec]"
+                .to_string(),
+        )
+    };
+    let invalid = |spec: &str| {
+        ShellError::InternalError(format!("kill: {}: invalid signal specification", spec))
+    };
+
+    let mut args = args.iter().peekable();
+    let mut signal = libc::SIGTERM;
+    match args.peek().map(|arg| arg.as_str()) {
+        Some("-l" | "-L") => {
+            args.next();
+            return list_signals(args.collect(), io);
+        }
+        Some("-s" | "-n") => {
+            args.next();
+            let spec = args.next().ok_or_else(usage)?;
+            signal = signal_number(spec).ok_or_else(|| invalid(spec))?;
+        }
+        Some("--") => {
+            args.next();
+        }
+        Some(arg) if arg.len() > 1 && arg.starts_with('-') => {
+            signal = signal_number(&arg[1..]).ok_or_else(|| invalid(&arg[1..]))?;
+            args.next();
+        }
+        _ => {}
+    }
+
+    let targets: Vec<&String> = args.collect();
+    if targets.is_empty() {
+        return Err(usage());
+    }
+
+    let mut status = 0;
+    for target in targets {
+        let result = if target.starts_with('%') {
+            shell.jobs.resolve(target).map(|id| {
+                let job = shell.jobs.get(id).unwrap();
+                let result = signal_result(unsafe { libc::killpg(job.pgid, signal) });
+                // A stopped job only acts on the signal once it runs again.
+                if let JobState::Stopped(_) = job.state
+                    && signal != libc::SIGKILL
+                    && signal != libc::SIGCONT
+                {
+                    unsafe { libc::killpg(job.pgid, libc::SIGCONT) };
+                }
+                result
+            })
+        } else {
+            match target.parse::<libc::pid_t>() {
+                Ok(pid) => Ok(signal_result(unsafe { libc::kill(pid, signal) })),
+                Err(_) => Err(format!("{}: arguments must be process or job IDs", target)),
+            }
+        };
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                writeln!(io.stderr, "kill: ({}) - {}", target, describe(&e))?;
+                status = 1;
+            }
+            Err(message) => {
+                writeln!(io.stderr, "kill: {}", message)?;
+                status = 1;
+            }
+        }
+    }
+    Ok(status)
+}
+
+/// `trap [-lp] [[action] condition ...]`. An action of `-`, or none before a
+/// single condition, resets the conditions; an empty action ignores them.
+fn execute_trap(shell: &mut Shell, args: &[String], io: &mut BuiltinIo) -> Result<i32, ShellError> {
+    let mut args = args;
+    let mut print = false;
+    while let Some(option) = args.first() {
+        match option.as_str() {
+            "-l" => return list_signals(vec![], io),
+            "-p" => print = true,
+            "--" => {
+                args = &args[1..];
+                break;
+            }
+            _ if option.starts_with('-') && option.len() > 1 && !option.starts_with("--") => {
+                return Err(ShellError::InternalError(format!(
+                    "trap: {}: invalid option",
+                    option
+                )));
+            }
+            _ => break,
+        }
+        args = &args[1..];
+    }
+
+    if args.is_empty() || print {
+        let mut conditions = vec![];
+        for spec in args {
+            match Condition::parse(spec) {
+                Some(condition) => conditions.push(condition),
+                None => {
+                    return Err(ShellError::InternalError(format!(
+                        "trap: {}: invalid signal specification",
+                        spec
+                    )));
+                }
+            }
+        }
+        for (condition, action) in shell.traps.actions() {
+            if conditions.is_empty() || conditions.contains(condition) {
+                writeln!(io.stdout, "trap -- {} {}", single_quote(action), condition)?;
+            }
+        }
+        return Ok(0);
+    }
+
+    // A lone operand, or a leading number, names conditions to reset.
+    let (action, specs) = if args.len() == 1 || args[0].parse::<u32>().is_ok() {
+        (None, args)
+    } else if args[0] == "-" {
+        (None, &args[1..])
+    } else {
+        (Some(&args[0]), &args[1..])
+    };
+
+    let mut status = 0;
+    for spec in specs {
+        let Some(condition) = Condition::parse(spec) else {
+            writeln!(io.stderr, "trap: {}: invalid signal specification", spec)?;
+            status = 1;
+            continue;
+        };
+        match action {
+            Some(action) => shell.traps.set(condition, action.clone()),
+            None => {
+                let ignored = shell.ignored_signals();
+                shell.traps.reset(condition, &ignored);
+            }
+        }
+    }
+    Ok(status)
+}
+
+fn signal_result(result: libc::c_int) -> io::Result<()> {
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// `kill -l`: every signal, or the names of the given signal numbers or
+/// statuses of processes killed by a signal.
+fn list_signals(specs: Vec<&String>, io: &mut BuiltinIo) -> Result<i32, ShellError> {
+    if specs.is_empty() {
+        for (i, (number, name, _)) in SIGNALS.iter().enumerate() {
+            let separator = if i % 5 == 4 || i == SIGNALS.len() - 1 {
+                '\n'
+            } else {
+                '\t'
+            };
+            write!(io.stdout, "{:2}) SIG{}{}", number, name, separator)?;
+        }
+        return Ok(0);
+    }
+
+    let mut status = 0;
+    for spec in specs {
+        let name = match spec.parse::<i32>() {
+            Ok(number) => {
+                signal_name(if number > 128 { number - 128 } else { number }).map(str::to_string)
+            }
+            Err(_) => signal_number(spec).map(|number| number.to_string()),
+        };
+        match name {
+            Some(name) => writeln!(io.stdout, "{}", name)?,
+            None => {
+                writeln!(io.stderr, "kill: {}: invalid signal specification", spec)?;
+                status = 1;
+            }
+        }
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        BuiltinIo, LIMITS, Limit, execute_command, execute_set, execute_type, parse_umask,
+        unwrap_prefixes, write_umask,
+    };
+    use crate::{
+        error::ShellError,
+        executor::{
+            expansion::{ExpandedCommand, expand_command},
+            substitution::Substitutions,
+        },
+        parser::{lexer::Token, parse_tokens},
+        shell::Shell,
+    };
+    use std::{
+        cell::RefCell,
+        env, fs,
+        io::{self, Write},
+        os::unix::fs::PermissionsExt,
+        path::PathBuf,
+        rc::Rc,
+    };
+
+    /// A writer whose output the test can read back.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Output {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The first command of `source`, expanded in a new shell.
+    fn parse(source: &str) -> ExpandedCommand {
+        let mut pipelines = parse_tokens(Token::tokenize(source).unwrap()).unwrap();
+        let command = pipelines.remove(0).commands.remove(0);
+        expand_command(
+            &mut Shell::default(),
+            &mut Substitutions::default(),
+            &command,
+        )
+        .unwrap()
+    }
+
+    /// Runs `set`, `type` or `command` and returns its status, output and
+    /// errors.
+    fn run(shell: &mut Shell, source: &str) -> (i32, String, String) {
+        let command = parse(source);
+        let (stdout, stderr) = (Output::default(), Output::default());
+        let mut io = BuiltinIo {
+            stdin: Box::new(io::empty()),
+            stdout: Box::new(stdout.clone()),
+            stderr: Box::new(stderr.clone()),
+        };
+        let status = match command.program.as_str() {
+            "set" => execute_set(shell, &command, &mut io),
+            "type" => execute_type(shell, &command, &mut io),
+            "command" => execute_command(shell, &command.arguments, &mut io),
+            program => panic!("{} is not tested here", program),
+        };
+        (status.unwrap(), stdout.text(), stderr.text())
+    }
+
+    /// A shell whose `PATH` is two new directories that both have an
+    /// executable `tool`.
+    fn shell_with_tools(name: &str) -> (Shell, PathBuf, PathBuf) {
+        let root = env::temp_dir().join(format!("shell-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        let (first, second) = (root.join("first"), root.join("second"));
+        for dir in [&first, &second] {
+            fs::create_dir_all(dir).unwrap();
+            let tool = dir.join("tool");
+            fs::write(&tool, "#!/bin/sh\n").unwrap();
+            fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let mut shell = Shell::default();
+        let path = format!("{}:{}", first.display(), second.display());
+        shell.set_var("PATH", path);
+        (shell, first.join("tool"), second.join("tool"))
+    }
+
+    #[test]
+    fn test_type_flags() {
+        let (mut shell, first, second) = shell_with_tools("type");
+        let (first, second) = (first.display(), second.display());
+
+        assert_eq!(
+            run(&mut shell, "type tool cd"),
+            (
+                0,
+                format!("tool is {}\ncd is a shell builtin\n", first),
+                String::new()
+            )
+        );
+        assert_eq!(
+            run(&mut shell, "type -a tool"),
+            (
+                0,
+                format!("tool is {}\ntool is {}\n", first, second),
+                String::new()
+            )
+        );
+        assert_eq!(
+            run(&mut shell, "type -t tool cd time"),
+            (0, "file\nbuiltin\nkeyword\n".to_string(), String::new())
+        );
+        assert_eq!(
+            run(&mut shell, "type -p cd tool"),
+            (0, format!("{}\n", first), String::new())
+        );
+        assert_eq!(
+            run(&mut shell, "type -P -a tool"),
+            (0, format!("{}\n{}\n", first, second), String::new())
+        );
+        assert_eq!(
+            run(&mut shell, "type -t nosuch"),
+            (1, String::new(), String::new())
+        );
+        assert!(matches!(
+            execute_type(&shell, &parse("type -x cd"), &mut BuiltinIo::standard()),
+            Err(ShellError::InternalError(message)) if message.starts_with("type: -x: invalid option")
+        ));
+    }
+
+    #[test]
+    fn test_command_flags() {
+        let (mut shell, first, _) = shell_with_tools("command");
+        let first = first.display();
+
+        assert_eq!(
+            run(&mut shell, "command -v cd tool nosuch"),
+            (1, format!("cd\n{}\n", first), String::new())
+        );
+        assert_eq!(
+            run(&mut shell, "command -V cd nosuch"),
+            (
+                1,
+                "cd is a shell builtin\n".to_string(),
+                "command: nosuch: not found\n".to_string()
+            )
+        );
+        assert_eq!(
+            run(&mut shell, "command -pv cd"),
+            (0, "cd\n".to_string(), String::new())
+        );
+        assert_eq!(
+            run(&mut shell, "command -x tool"),
+            (
+                2,
+                String::new(),
+                "command: -x: invalid option\ncommand: usage: command [-pVv] command [arg ...]\n"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_unwrap_prefixes() {
+        let unwrap = |source: &str| {
+            let mut command = parse(source);
+            unwrap_prefixes(&mut command).map(|()| (command.program, command.arguments))
+        };
+        let strings = |words: &[&str]| words.iter().map(|word| word.to_string()).collect();
+
+        assert_eq!(
+            unwrap("command tool -x").unwrap(),
+            ("tool".to_string(), strings(&["-x"]))
+        );
+        assert_eq!(
+            unwrap("command -- builtin echo hi").unwrap(),
+            ("echo".to_string(), strings(&["hi"]))
+        );
+        let (program, _) = unwrap("command -p sh").unwrap();
+        assert!(program.starts_with('/') && program.ends_with("/sh"));
+        assert_eq!(unwrap("command -p cd").unwrap(), ("cd".to_string(), vec![]));
+
+        // Lookups, options it does not know and a missing name are left for
+        // `command` itself.
+        for source in [
+            "command -v tool",
+            "command -V tool",
+            "command -x tool",
+            "command -p",
+        ] {
+            assert_eq!(unwrap(source).unwrap().0, "command", "{}", source);
+        }
+        assert_eq!(unwrap("builtin").unwrap().0, "builtin");
+        assert!(matches!(
+            unwrap("builtin tool"),
+            Err(ShellError::InternalError(message)) if message == "builtin: tool: not a shell builtin"
+        ));
+    }
+
+    fn limit(flag: char) -> &'static Limit {
+        LIMITS.iter().find(|limit| limit.flag == flag).unwrap()
+    }
+
+    #[test]
+    fn test_limit_values_are_scaled_by_unit() {
+        let current = libc::rlimit {
+            rlim_cur: 2048,
+            rlim_max: libc::RLIM_INFINITY,
+        };
+        let parse = |flag, value| limit(flag).parse(value, &current).ok();
+
+        assert_eq!(parse('f', "4"), Some(2048));
+        assert_eq!(parse('c', "0"), Some(0));
+        assert_eq!(parse('d', "4"), Some(4096));
+        assert_eq!(parse('n', "4"), Some(4));
+        assert_eq!(parse('n', "unlimited"), Some(libc::RLIM_INFINITY));
+        assert_eq!(parse('f', "soft"), Some(2048));
+        assert_eq!(parse('f', "hard"), Some(libc::RLIM_INFINITY));
+        assert_eq!(parse('f', "-1"), None);
+        assert_eq!(parse('f', "4k"), None);
+        assert_eq!(parse('d', &libc::rlim_t::MAX.to_string()), None);
+
+        assert_eq!(limit('f').show(2048), "4");
+        assert_eq!(limit('d').show(4096), "4");
+        assert_eq!(limit('v').show(libc::RLIM_INFINITY), "unlimited");
+        assert_eq!(
+            limit('f').describe(2048),
+            "file size                   (blocks, -f) 4"
+        );
+        assert_eq!(
+            limit('n').describe(1024),
+            "open files                          (-n) 1024"
+        );
+    }
+
+    #[test]
+    fn test_umask_modes() {
+        let umask = |mode| parse_umask(mode, 0o022).map_err(|e| e.message());
+
+        assert_eq!(umask("077"), Ok(0o077));
+        assert_eq!(umask("0"), Ok(0));
+        assert_eq!(umask("0777"), Ok(0o777));
+        assert_eq!(
+            umask("1000"),
+            Err("umask: 1000: octal number out of range".to_string())
+        );
+        assert_eq!(
+            umask("08"),
+            Err("umask: 08: octal number out of range".to_string())
+        );
+
+        assert_eq!(umask("u=rwx,g=rx,o="), Ok(0o027));
+        assert_eq!(umask("g-w"), Ok(0o022));
+        assert_eq!(umask("g+w,o-r"), Ok(0o006));
+        assert_eq!(umask("a+x"), Ok(0o022));
+        assert_eq!(umask("a-x"), Ok(0o133));
+        assert_eq!(umask("-w"), Ok(0o222));
+        assert_eq!(umask("ug=r-w+x"), Ok(0o222));
+        assert_eq!(umask("u=,go=rwx"), Ok(0o700));
+
+        assert_eq!(
+            umask("u*r"),
+            Err("umask: `*': invalid symbolic mode operator".to_string())
+        );
+        assert_eq!(
+            umask("u+z"),
+            Err("umask: `z': invalid symbolic mode character".to_string())
+        );
+        assert_eq!(
+            umask("ug"),
+            Err("umask: missing symbolic mode operator".to_string())
+        );
+    }
+
+    #[test]
+    fn test_umask_output() {
+        let show = |mask, symbolic, reusable| {
+            let stdout = Output::default();
+            let mut io = BuiltinIo {
+                stdin: Box::new(io::empty()),
+                stdout: Box::new(stdout.clone()),
+                stderr: Box::new(io::sink()),
+            };
+            write_umask(mask, symbolic, reusable, &mut io).unwrap();
+            stdout.text()
+        };
+
+        assert_eq!(show(0o022, false, false), "0022\n");
+        assert_eq!(show(0o027, true, false), "u=rwx,g=rx,o=\n");
+        assert_eq!(show(0o777, true, false), "u=,g=,o=\n");
+        assert_eq!(show(0o002, false, true), "umask 0002\n");
+        assert_eq!(show(0o077, true, true), "umask -S u=rwx,g=,o=\n");
+    }
+
+    #[test]
+    fn test_set_options_and_positional_parameters() {
+        let mut shell = Shell::default();
+        let strings =
+            |words: &[&str]| -> Vec<String> { words.iter().map(|word| word.to_string()).collect() };
+
+        assert_eq!(
+            run(&mut shell, "set -eu -- a -b"),
+            (0, String::new(), String::new())
+        );
+        assert!(shell.errexit && shell.nounset);
+        assert_eq!(shell.positional_params, strings(&["a", "-b"]));
+
+        assert_eq!(
+            run(&mut shell, "set +e c -d"),
+            (0, String::new(), String::new())
+        );
+        assert!(!shell.errexit);
+        assert_eq!(shell.positional_params, strings(&["c", "-d"]));
+
+        run(&mut shell, "set -o pipefail --");
+        assert!(shell.pipefail);
+        assert!(shell.positional_params.is_empty());
+
+        let (status, stdout, _) = run(&mut shell, "set +o");
+        assert_eq!(status, 0);
+        assert!(stdout.contains("set -o nounset\nset -o pipefail\n"));
+    }
+}
@@ -0,0 +1,371 @@
+use std::{
+    ffi::CString,
+    fs::{self, Metadata},
+    os::unix::fs::{FileTypeExt, MetadataExt},
+};
+
+use crate::{
+    error::ShellError, executor::expansion::expand_unsplit, parser::ast::ConditionalExpression,
+    shell::Shell,
+};
+
+/// Runs a `[[ ... ]]` command: 0 if the expression is true, 1 if it is false,
+/// and 2 if it cannot be evaluated, such as for an invalid regex.
+pub fn execute_conditional(
+    shell: &mut Shell,
+    expression: &ConditionalExpression,
+) -> Result<i32, ShellError> {
+    match evaluate(shell, expression) {
+        Ok(matched) => Ok(if matched { 0 } else { 1 }),
+        Err(e @ ShellError::InternalError(_)) => {
+            Shell::report_error(&e);
+            Ok(2)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn evaluate(shell: &mut Shell, expression: &ConditionalExpression) -> Result<bool, ShellError> {
+    match expression {
+        ConditionalExpression::Word(word) => Ok(!expand_unsplit(shell, word, None)?.is_empty()),
+        ConditionalExpression::Not(inner) => Ok(!evaluate(shell, inner)?),
+        ConditionalExpression::And(left, right) => {
+            Ok(evaluate(shell, left)? && evaluate(shell, right)?)
+        }
+        ConditionalExpression::Or(left, right) => {
+            Ok(evaluate(shell, left)? || evaluate(shell, right)?)
+        }
+        ConditionalExpression::Unary { operator, operand } => {
+            let operand = expand_unsplit(shell, operand, None)?;
+            unary_test(shell, operator, &operand)
+        }
+        ConditionalExpression::Binary {
+            operator,
+            left,
+            right,
+        } => {
+            let left = expand_unsplit(shell, left, None)?;
+            match operator.as_str() {
+                "=" | "==" | "!=" => {
+                    let pattern = expand_unsplit(shell, right, Some(escape_pattern))?;
+                    let matched = fnmatch(&pattern, &left)?;
+                    Ok(matched == (operator != "!="))
+                }
+                "=~" => {
+                    let regex = expand_unsplit(shell, right, Some(escape_regex))?;
+                    regex_match(shell, &regex, &left)
+                }
+                _ => {
+                    let right = expand_unsplit(shell, right, None)?;
+                    binary_test(operator, &left, &right)
+                }
+            }
+        }
+    }
+}
+
+fn unary_test(shell: &Shell, operator: &str, operand: &str) -> Result<bool, ShellError> {
+    let metadata = || fs::metadata(operand).ok();
+    let is = |check: fn(&Metadata) -> bool| metadata().is_some_and(|m| check(&m));
+
+    Ok(match operator {
+        "-z" => operand.is_empty(),
+        "-n" => !operand.is_empty(),
+        "-v" => shell.get_var(operand).is_some() || shell.arrays.contains_key(operand),
+        "-a" | "-e" => metadata().is_some(),
+        "-f" => is(|m| m.is_file()),
+        "-d" => is(|m| m.is_dir()),
+        "-b" => is(|m| m.file_type().is_block_device()),
+        "-c" => is(|m| m.file_type().is_char_device()),
+        "-p" => is(|m| m.file_type().is_fifo()),
+        "-S" => is(|m| m.file_type().is_socket()),
+        "-s" => is(|m| m.len() > 0),
+        "-g" => is(|m| m.mode() & libc::S_ISGID != 0),
+        "-u" => is(|m| m.mode() & libc::S_ISUID != 0),
+        "-k" => is(|m| m.mode() & libc::S_ISVTX != 0),
+        "-O" => is(|m| m.uid() == unsafe { libc::geteuid() }),
+        "-G" => is(|m| m.gid() == unsafe { libc::getegid() }),
+        "-h" | "-L" => fs::symlink_metadata(operand).is_ok_and(|m| m.file_type().is_symlink()),
+        "-r" => access(operand, libc::R_OK),
+        "-w" => access(operand, libc::W_OK),
+        "-x" => access(operand, libc::X_OK),
+        "-t" => match operand.parse::<i32>() {
+            Ok(fd) => unsafe { libc::isatty(fd) == 1 },
+            Err(_) => false,
+        },
+        _ => {
+            return Err(ShellError::InternalError(format!(
+                "{}: unary operator expected",
+                operator
+            )));
+        }
+    })
+}
+
+fn binary_test(operator: &str, left: &str, right: &str) -> Result<bool, ShellError> {
+    match operator {
+        "<" => return Ok(left < right),
+        ">" => return Ok(left > right),
+        "-nt" | "-ot" => {
+            let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+            let (left, right) = (modified(left), modified(right));
+            return Ok(match operator {
+                "-nt" => left.is_some() && (right.is_none() || left > right),
+                _ => right.is_some() && (left.is_none() || left < right),
+            });
+        }
+        "-ef" => {
+            return Ok(match (fs::metadata(left), fs::metadata(right)) {
+                (Ok(l), Ok(r)) => l.dev() == r.dev() && l.ino() == r.ino(),
+                _ => false,
+            });
+        }
+        _ => {}
+    }
+
+    let integer = |value: &str| {
+        value.trim().parse::<i64>().map_err(|_| {
+            ShellError::InternalError(format!("{}: integer expression expected", value))
+        })
+    };
+    let (left, right) = (integer(left)?, integer(right)?);
+    Ok(match operator {
+        "-eq" => left == right,
+        "-ne" => left != right,
+        "-lt" => left < right,
+        "-le" => left <= right,
+        "-gt" => left > right,
+        "-ge" => left >= right,
+        _ => {
+            return Err(ShellError::InternalError(format!(
+                "{}: binary operator expected",
+                operator
+            )));
+        }
+    })
+}
+
+fn access(path: &str, mode: libc::c_int) -> bool {
+    CString::new(path).is_ok_and(|path| unsafe { libc::access(path.as_ptr(), mode) == 0 })
+}
+
+fn escape_pattern(text: &str) -> String {
+    escape(text, "*?[]\\")
+}
+
+fn escape_regex(text: &str) -> String {
+    escape(text, ".[]()*+?{}|^$\\")
+}
+
+fn escape(text: &str, special: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if special.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn c_string(text: &str) -> Result<CString, ShellError> {
+    CString::new(text)
+        .map_err(|_| ShellError::InternalError("conditional: string contains NUL".to_string()))
+}
+
+fn fnmatch(pattern: &str, text: &str) -> Result<bool, ShellError> {
+    let (pattern, text) = (c_string(pattern)?, c_string(text)?);
+    Ok(unsafe { libc::fnmatch(pattern.as_ptr(), text.as_ptr(), 0) } == 0)
+}
+
+/// Where `regex_t` keeps `re_nsub`, which POSIX makes public but the `libc`
+/// crate declares private: glibc puts it after six word-sized fields, musl
+/// first, and the BSDs after an `int`.
+#[cfg(target_env = "gnu")]
+const NSUB_OFFSET: usize = 6 * size_of::<usize>();
+#[cfg(target_env = "musl")]
+const NSUB_OFFSET: usize = 0;
+#[cfg(not(any(target_env = "gnu", target_env = "musl")))]
+const NSUB_OFFSET: usize = size_of::<usize>();
+
+/// The number of parenthesized subexpressions `regcomp` found.
+fn subexpression_count(compiled: &libc::regex_t) -> usize {
+    unsafe {
+        (compiled as *const libc::regex_t)
+            .cast::<u8>()
+            .add(NSUB_OFFSET)
+            .cast::<usize>()
+            .read()
+    }
+}
+
+/// Matches `text` against the POSIX extended regular expression `regex`,
+/// storing the match and its subexpressions in `BASH_REMATCH`.
+fn regex_match(shell: &mut Shell, regex: &str, text: &str) -> Result<bool, ShellError> {
+    let (pattern, subject) = (c_string(regex)?, c_string(text)?);
+
+    let mut compiled: libc::regex_t = unsafe { std::mem::zeroed() };
+    if unsafe { libc::regcomp(&mut compiled, pattern.as_ptr(), libc::REG_EXTENDED) } != 0 {
+        return Err(ShellError::InternalError(format!(
+            "{}: invalid regular expression",
+            regex
+        )));
+    }
+
+    let mut matches = vec![
+        libc::regmatch_t {
+            rm_so: -1,
+            rm_eo: -1
+        };
+        subexpression_count(&compiled) + 1
+    ];
+    let status = unsafe {
+        libc::regexec(
+            &compiled,
+            subject.as_ptr(),
+            matches.len(),
+            matches.as_mut_ptr(),
+            0,
+        )
+    };
+    unsafe { libc::regfree(&mut compiled) };
+
+    if status != 0 {
+        shell.arrays.insert("BASH_REMATCH".to_string(), Vec::new());
+        return Ok(false);
+    }
+
+    let groups = matches
+        .iter()
+        .map(|m| {
+            if m.rm_so < 0 {
+                String::new()
+            } else {
+                String::from_utf8_lossy(&text.as_bytes()[m.rm_so as usize..m.rm_eo as usize])
+                    .into_owned()
+            }
+        })
+        .collect();
+    shell.arrays.insert("BASH_REMATCH".to_string(), groups);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::execute_conditional;
+    use crate::{
+        error::ShellError,
+        parser::{ast::Pipeline, lexer::Token, parse_tokens},
+        shell::Shell,
+    };
+    use std::{
+        env,
+        fs::{self, File},
+        time::{Duration, SystemTime},
+    };
+
+    fn parse(source: &str) -> Result<Vec<Pipeline>, ShellError> {
+        parse_tokens(Token::tokenize(source)?)
+    }
+
+    /// The status of `[[ expression ]]`.
+    fn test(shell: &mut Shell, expression: &str) -> i32 {
+        let pipelines = parse(&format!("[[ {} ]]", expression)).unwrap();
+        let conditional = pipelines[0].commands[0].conditional.as_ref().unwrap();
+        execute_conditional(shell, conditional).unwrap()
+    }
+
+    #[test]
+    fn test_pattern_matching() {
+        let mut shell = Shell::default();
+        shell.set_var("file", "notes.txt".to_string());
+        assert_eq!(test(&mut shell, "$file == *.txt"), 0);
+        assert_eq!(test(&mut shell, "$file = n?tes.[st]xt"), 0);
+        assert_eq!(test(&mut shell, "$file != *.txt"), 1);
+        assert_eq!(test(&mut shell, "$file == '*.txt'"), 1);
+        assert_eq!(test(&mut shell, "'*.txt' == \"*.txt\""), 0);
+        assert_eq!(test(&mut shell, "$file"), 0);
+        assert_eq!(test(&mut shell, "$unset"), 1);
+    }
+
+    #[test]
+    fn test_regex_sets_bash_rematch() {
+        let mut shell = Shell::default();
+        assert_eq!(test(&mut shell, "key=value =~ ^([a-z]+)=(x)?(.*)$"), 0);
+        assert_eq!(
+            shell.arrays["BASH_REMATCH"],
+            ["key=value", "key", "", "value"]
+        );
+        assert_eq!(test(&mut shell, "a.c =~ '.'c"), 0);
+        assert_eq!(shell.arrays["BASH_REMATCH"], [".c"]);
+        assert_eq!(test(&mut shell, "abc =~ '.'c"), 1);
+        assert!(shell.arrays["BASH_REMATCH"].is_empty());
+        assert_eq!(test(&mut shell, "a =~ ["), 2);
+    }
+
+    #[test]
+    fn test_file_tests() {
+        let dir = env::temp_dir().join(format!("shell-{}-conditional", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (old, new) = (dir.join("old"), dir.join("new"));
+        let now = SystemTime::now();
+        File::create(&old)
+            .unwrap()
+            .set_modified(now - Duration::from_secs(60))
+            .unwrap();
+        File::create(&new).unwrap().set_modified(now).unwrap();
+        let (dir, old, new) = (dir.display(), old.display(), new.display());
+
+        let mut shell = Shell::default();
+        assert_eq!(test(&mut shell, &format!("-d {}", dir)), 0);
+        assert_eq!(test(&mut shell, &format!("-f {}", dir)), 1);
+        assert_eq!(test(&mut shell, &format!("-f {}", old)), 0);
+        assert_eq!(test(&mut shell, &format!("-s {}", old)), 1);
+        assert_eq!(test(&mut shell, &format!("-e {}/missing", dir)), 1);
+        assert_eq!(test(&mut shell, &format!("{} -nt {}", new, old)), 0);
+        assert_eq!(test(&mut shell, &format!("{} -ot {}", new, old)), 1);
+        assert_eq!(test(&mut shell, &format!("{} -ot {}", old, new)), 0);
+        assert_eq!(test(&mut shell, &format!("{} -nt {}/missing", old, dir)), 0);
+        assert_eq!(test(&mut shell, &format!("{} -ef {}/./old", old, dir)), 0);
+        fs::remove_dir_all(dir.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_integer_comparisons() {
+        let mut shell = Shell::default();
+        assert_eq!(test(&mut shell, "10 -gt 9"), 0);
+        assert_eq!(test(&mut shell, "10 > 9"), 1);
+        assert_eq!(test(&mut shell, "-3 -lt 2"), 0);
+        assert_eq!(test(&mut shell, "2 -le 2 && 2 -ge 2 && 2 -eq 2"), 0);
+        assert_eq!(test(&mut shell, "2 -ne 2"), 1);
+        assert_eq!(test(&mut shell, "x -eq 1"), 2);
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        let mut shell = Shell::default();
+        assert_eq!(test(&mut shell, "a || b && ''"), 0);
+        assert_eq!(test(&mut shell, "( a || b ) && ''"), 1);
+        assert_eq!(test(&mut shell, "! '' && a"), 0);
+        assert_eq!(test(&mut shell, "! ( a && '' )"), 0);
+        assert_eq!(test(&mut shell, "! ! a"), 0);
+        assert_eq!(test(&mut shell, "-n a && -z ''"), 0);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for source in [
+            "[[ a == ]]",
+            "[[ ( a ]]",
+            "[[ a b ]]",
+            "[[ a && ]]",
+            "[[ ! ]]",
+        ] {
+            assert!(
+                matches!(parse(source), Err(ShellError::ParseError(_))),
+                "{}",
+                source
+            );
+        }
+    }
+}
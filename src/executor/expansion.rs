@@ -0,0 +1,411 @@
+use std::os::fd::RawFd;
+
+use crate::{
+    error::ShellError,
+    executor::substitution::Substitutions,
+    parser::ast::{Command, ConditionalExpression, Redirection, Word, WordPart},
+    shell::Shell,
+};
+
+const DEFAULT_IFS: &str = " \t\n";
+
+/// A command whose words have been expanded, along with the values of its
+/// assignments and the process substitution descriptors it has to inherit.
+pub struct ExpandedCommand {
+    /// The first field of the expanded words, empty if there are none.
+    pub program: String,
+    pub arguments: Vec<String>,
+    /// With their paths expanded.
+    pub redirections: Vec<Redirection>,
+    /// A `[[ ... ]]` expression, whose operands are expanded as it runs.
+    pub conditional: Option<ConditionalExpression>,
+    pub assignments: Vec<(String, String)>,
+    pub inherited_fds: Vec<RawFd>,
+}
+
+/// Expands the command's words and redirection targets into the program
+/// name, arguments and redirection paths they stand for.
+pub fn expand_command(
+    shell: &mut Shell,
+    substitutions: &mut Substitutions,
+    command: &Command,
+) -> Result<ExpandedCommand, ShellError> {
+    let mut inherited_fds = Vec::new();
+
+    // Each value sees the assignments before it, as in `x=1 y=$x`.
+    let mut assignments: Vec<(String, String)> = Vec::new();
+    for assignment in &command.assignments {
+        let value = with_assignments(shell, &assignments, |shell| {
+            expand_unsplit(shell, &assignment.value, None)
+        })?;
+        assignments.push((assignment.name.clone(), value));
+    }
+
+    let mut fields = Vec::new();
+    for word in &command.words {
+        fields.extend(expand_word(shell, substitutions, word, &mut inherited_fds)?);
+    }
+
+    let redirections = command
+        .redirections
+        .iter()
+        .map(|redirection| {
+            expand_redirection(shell, substitutions, redirection, &mut inherited_fds)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut fields = fields.into_iter();
+    Ok(ExpandedCommand {
+        program: fields.next().unwrap_or_default(),
+        arguments: fields.collect(),
+        redirections,
+        conditional: command.conditional.clone(),
+        assignments,
+        inherited_fds,
+    })
+}
+
+/// Runs `run` with the assignments of a command set as shell variables, then
+/// restores the variables they replaced.
+pub fn with_assignments<T>(
+    shell: &mut Shell,
+    assignments: &[(String, String)],
+    run: impl FnOnce(&mut Shell) -> T,
+) -> T {
+    let saved: Vec<(String, Option<String>)> = assignments
+        .iter()
+        .map(|(name, value)| {
+            let previous = shell.environment_var.insert(name.clone(), value.clone());
+            (name.clone(), previous)
+        })
+        .collect();
+    let result = run(shell);
+    for (name, previous) in saved.into_iter().rev() {
+        match previous {
+            Some(value) => shell.environment_var.insert(name, value),
+            None => shell.environment_var.remove(&name),
+        };
+    }
+    result
+}
+
+fn expand_redirection(
+    shell: &mut Shell,
+    substitutions: &mut Substitutions,
+    redirection: &Redirection,
+    inherited_fds: &mut Vec<RawFd>,
+) -> Result<Redirection, ShellError> {
+    let mut fields = expand_word(shell, substitutions, &redirection.target, inherited_fds)?;
+    if fields.len() != 1 {
+        return Err(ShellError::InternalError("ambiguous redirect".to_string()));
+    }
+
+    Ok(Redirection {
+        path: fields.remove(0),
+        ..redirection.clone()
+    })
+}
+
+/// Expands one word into zero or more fields. Unquoted expansion results are
+/// split on `$IFS`; quoted ones never are.
+pub fn expand_word(
+    shell: &mut Shell,
+    substitutions: &mut Substitutions,
+    word: &Word,
+    inherited_fds: &mut Vec<RawFd>,
+) -> Result<Vec<String>, ShellError> {
+    let ifs = shell
+        .get_var("IFS")
+        .unwrap_or_else(|| DEFAULT_IFS.to_string());
+    let mut splitter = FieldSplitter::new(&ifs);
+
+    for part in &word.parts {
+        match part {
+            WordPart::Literal(text) | WordPart::Quoted(text) => splitter.push_literal(text),
+            WordPart::Parameter { name, quoted } => {
+                if let Some((values, joined)) = parameter_list(shell, name) {
+                    if !quoted {
+                        for (i, value) in values.iter().enumerate() {
+                            if i > 0 {
+                                splitter.end_field();
+                            }
+                            splitter.push_split(value);
+                        }
+                    } else if !joined {
+                        for (i, value) in values.iter().enumerate() {
+                            if i > 0 {
+                                splitter.end_field();
+                            }
+                            splitter.push_literal(value);
+                        }
+                    } else {
+                        let separator = ifs.chars().next().map(String::from).unwrap_or_default();
+                        splitter.push_literal(&values.join(&separator));
+                    }
+                } else {
+                    let value = required_value(shell, name)?;
+                    if *quoted {
+                        splitter.push_literal(&value);
+                    } else {
+                        splitter.push_split(&value);
+                    }
+                }
+            }
+            WordPart::ProcessSubstitution(substitution) => {
+                let fd = substitutions.start(shell, substitution)?;
+                inherited_fds.push(fd);
+                splitter.push_literal(&format!("/dev/fd/{}", fd));
+            }
+        }
+    }
+
+    Ok(splitter.finish())
+}
+
+/// Expands a word inside `[[ ]]`, where no field splitting takes place. With
+/// `escape`, quoted text is passed through it so that a pattern or regular
+/// expression matches that text literally.
+pub fn expand_unsplit(
+    shell: &Shell,
+    word: &Word,
+    escape: Option<fn(&str) -> String>,
+) -> Result<String, ShellError> {
+    let escape = |text: &str| match escape {
+        Some(escape) => escape(text),
+        None => text.to_string(),
+    };
+
+    let mut expanded = String::new();
+    for part in &word.parts {
+        match part {
+            WordPart::Literal(text) => expanded.push_str(text),
+            WordPart::Quoted(text) => expanded.push_str(&escape(text)),
+            WordPart::Parameter { name, quoted } => {
+                let value = match parameter_list(shell, name) {
+                    Some((values, _)) => values.join(" "),
+                    None => required_value(shell, name)?,
+                };
+                if *quoted {
+                    expanded.push_str(&escape(&value));
+                } else {
+                    expanded.push_str(&value);
+                }
+            }
+            WordPart::ProcessSubstitution(_) => {}
+        }
+    }
+    Ok(expanded)
+}
+
+/// The values of a parameter that expands to a list, `$@`, `$*`,
+/// `${name[@]}` or `${name[*]}`, and whether a quoted expansion joins them
+/// into one field.
+fn parameter_list(shell: &Shell, name: &str) -> Option<(Vec<String>, bool)> {
+    match name {
+        "@" => return Some((shell.positional_params.clone(), false)),
+        "*" => return Some((shell.positional_params.clone(), true)),
+        _ => {}
+    }
+
+    let (array, joined) = if let Some(array) = name.strip_suffix("[@]") {
+        (array, false)
+    } else {
+        (name.strip_suffix("[*]")?, true)
+    };
+    let values = match shell.arrays.get(array) {
+        Some(values) => values.clone(),
+        None => shell.get_var(array).into_iter().collect(),
+    };
+    Some((values, joined))
+}
+
+/// A parameter's value, empty if it is unset, or with `set -u` an error.
+fn required_value(shell: &Shell, name: &str) -> Result<String, ShellError> {
+    match parameter_value(shell, name) {
+        Some(value) => Ok(value),
+        None if shell.nounset => {
+            let special = !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+            let shown = if special {
+                format!("${}", name)
+            } else {
+                name.to_string()
+            };
+            Err(ShellError::Unbound(shown))
+        }
+        None => Ok(String::new()),
+    }
+}
+
+/// Resolves positional and special parameters, array elements, then shell
+/// and environment variables. A plain array name means its first element.
+fn parameter_value(shell: &Shell, name: &str) -> Option<String> {
+    if let Some((array, subscript)) = name.split_once('[') {
+        let index: usize = subscript.trim_end_matches(']').parse().ok()?;
+        return match shell.arrays.get(array) {
+            Some(values) => values.get(index).cloned(),
+            None if index == 0 => shell.get_var(array),
+            None => None,
+        };
+    }
+
+    if let Ok(index) = name.parse::<usize>() {
+        return if index == 0 {
+            Some(shell.shell_name.clone())
+        } else {
+            shell.positional_params.get(index - 1).cloned()
+        };
+    }
+
+    match name {
+        "#" => Some(shell.positional_params.len().to_string()),
+        "?" => Some(shell.last_status.to_string()),
+        "$" => Some(std::process::id().to_string()),
+        "!" => shell.last_background_pid.map(|pid| pid.to_string()),
+        "-" => Some(shell.option_flags()),
+        "LINENO" => Some(shell.line_number.to_string()),
+        _ => match shell.arrays.get(name) {
+            Some(values) => values.first().cloned(),
+            None => shell.get_var(name),
+        },
+    }
+}
+
+/// Builds the fields of a word, carrying the field in progress across the
+/// word's parts so that `pre$var` and `"$@"post` join up correctly.
+struct FieldSplitter<'a> {
+    ifs: &'a str,
+    fields: Vec<String>,
+    current: Option<String>,
+}
+
+impl<'a> FieldSplitter<'a> {
+    fn new(ifs: &'a str) -> Self {
+        FieldSplitter {
+            ifs,
+            fields: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Appends text that is not split. Even empty text creates a field, which
+    /// is how `""` and `"$empty"` survive while `$empty` vanishes.
+    fn push_literal(&mut self, text: &str) {
+        self.current.get_or_insert_with(String::new).push_str(text);
+    }
+
+    /// Appends an unquoted expansion result, splitting it on `$IFS`. IFS
+    /// whitespace runs collapse and never produce empty fields; each other IFS
+    /// character, together with any surrounding IFS whitespace, ends exactly
+    /// one field, even an empty one.
+    fn push_split(&mut self, value: &str) {
+        if self.ifs.is_empty() {
+            if !value.is_empty() {
+                self.push_literal(value);
+            }
+            return;
+        }
+
+        let ifs = self.ifs;
+        let is_space = |c: char| matches!(c, ' ' | '\t' | '\n') && ifs.contains(c);
+        let is_delimiter = |c: char| !is_space(c) && ifs.contains(c);
+
+        let mut chars = value.chars().peekable();
+        while let Some(c) = chars.next() {
+            if is_space(c) {
+                let had_field = self.current.is_some();
+                self.end_field();
+                while chars.next_if(|&c| is_space(c)).is_some() {}
+                if chars.next_if(|&c| is_delimiter(c)).is_some() {
+                    if !had_field {
+                        self.fields.push(String::new());
+                    }
+                    while chars.next_if(|&c| is_space(c)).is_some() {}
+                }
+            } else if is_delimiter(c) {
+                self.fields.push(self.current.take().unwrap_or_default());
+                while chars.next_if(|&c| is_space(c)).is_some() {}
+            } else {
+                self.current.get_or_insert_with(String::new).push(c);
+            }
+        }
+    }
+
+    fn end_field(&mut self) {
+        if let Some(field) = self.current.take() {
+            self.fields.push(field);
+        }
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        self.end_field();
+        self.fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DEFAULT_IFS, FieldSplitter, required_value};
+    use crate::{error::ShellError, shell::Shell};
+
+    fn split(ifs: &str, value: &str) -> Vec<String> {
+        let mut splitter = FieldSplitter::new(ifs);
+        splitter.push_split(value);
+        splitter.finish()
+    }
+
+    #[test]
+    fn test_whitespace_ifs_collapses() {
+        assert_eq!(split(DEFAULT_IFS, "  a \t b\n"), vec!["a", "b"]);
+        assert!(split(DEFAULT_IFS, "   ").is_empty());
+    }
+
+    #[test]
+    fn test_non_whitespace_ifs_keeps_empty_fields() {
+        assert_eq!(split(":", "a::b:"), vec!["a", "", "b"]);
+        assert_eq!(split(" :", " a : b "), vec!["a", "b"]);
+        assert_eq!(split(" :", " :b"), vec!["", "b"]);
+    }
+
+    #[test]
+    fn test_empty_ifs_does_not_split() {
+        assert_eq!(split("", "a b"), vec!["a b"]);
+        assert!(split("", "").is_empty());
+    }
+
+    #[test]
+    fn test_fields_join_across_parts() {
+        let mut splitter = FieldSplitter::new(DEFAULT_IFS);
+        splitter.push_literal("pre");
+        splitter.push_split("a b");
+        splitter.push_literal("post");
+        assert_eq!(splitter.finish(), vec!["prea", "bpost"]);
+    }
+
+    #[test]
+    fn test_nounset_rejects_unset_parameters() {
+        let mut shell = Shell::default();
+        assert_eq!(required_value(&shell, "__unset__").unwrap(), "");
+
+        shell.nounset = true;
+        assert!(matches!(
+            required_value(&shell, "__unset__"),
+            Err(ShellError::Unbound(name)) if name == "__unset__"
+        ));
+        assert!(matches!(
+            required_value(&shell, "!"),
+            Err(ShellError::Unbound(name)) if name == "$!"
+        ));
+        assert_eq!(required_value(&shell, "#").unwrap(), "0");
+    }
+
+    #[test]
+    fn test_dash_lists_short_options() {
+        let mut shell = Shell::default();
+        assert_eq!(required_value(&shell, "-").unwrap(), "");
+
+        (shell.errexit, shell.nounset, shell.pipefail) = (true, true, true);
+        assert_eq!(required_value(&shell, "-").unwrap(), "eu");
+        (shell.interactive, shell.verbose, shell.xtrace) = (true, true, true);
+        assert_eq!(required_value(&shell, "-").unwrap(), "eiuvx");
+    }
+}
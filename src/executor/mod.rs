@@ -1,94 +1,768 @@
 pub mod builtins;
+pub mod conditional;
+pub mod expansion;
+pub mod redirection;
+pub mod substitution;
+pub mod timing;
 
 use crate::{
     error::ShellError,
-    executor::builtins::{execute_builtin, is_builtin},
-    parser::ast::Pipeline,
-    shell::Shell,
+    executor::{
+        builtins::{execute_builtin, is_builtin, unwrap_prefixes},
+        conditional::execute_conditional,
+        expansion::{ExpandedCommand, expand_command, with_assignments},
+        redirection::{apply_redirections, open_redirections},
+        substitution::Substitutions,
+        timing::Stopwatch,
+    },
+    parser::{ast::Pipeline, printer::quote},
+    shell::{
+        Shell,
+        jobs::{JobState, Process, Terminal},
+        signals::{reset_signals, termination_message},
+    },
+};
+use std::{
+    io::{self, Write},
+    os::{
+        fd::{AsRawFd, OwnedFd, RawFd},
+        unix::process::{CommandExt, ExitStatusExt},
+    },
+    process::{Child, Command, ExitStatus, Stdio},
 };
-use std::process::{Command, Stdio};
 
 pub fn execute_pipeline(shell: &mut Shell, pipeline: Pipeline) -> Result<i32, ShellError> {
-    if pipeline.commands.is_empty() {
-        return Ok(0);
+    if pipeline.background {
+        return start_job(shell, pipeline);
     }
+    execute_foreground(shell, pipeline, false)
+}
+
+/// Runs a pipeline and waits for it. With `replace`, a pipeline of one
+/// external command execs it in place of the shell process.
+fn execute_foreground(
+    shell: &mut Shell,
+    pipeline: Pipeline,
+    replace: bool,
+) -> Result<i32, ShellError> {
+    let stopwatch = pipeline.time.map(|_| Stopwatch::start());
+
+    let result = if pipeline.commands.is_empty() {
+        Ok(0)
+    } else {
+        let mut substitutions = Substitutions::default();
+        let result = run_pipeline(shell, &pipeline, &mut substitutions, replace);
+        substitutions.reap();
+        result.map(|statuses| pipeline_status(shell, statuses))
+    };
+
+    if let (Some(format), Some(stopwatch)) = (pipeline.time, stopwatch) {
+        let timeformat = shell.get_var("TIMEFORMAT");
+        eprint!(
+            "{}",
+            timing::report(format, timeformat.as_deref(), &stopwatch.stop())
+        );
+    }
+
+    if pipeline.negated {
+        return result.map(|status| if status == 0 { 1 } else { 0 });
+    }
+    result
+}
 
-    if pipeline.commands.len() == 1 {
-        let command = &pipeline.commands[0];
-        if is_builtin(&command.program) {
-            return execute_builtin(shell, command);
+/// Starts a pipeline in the background, in a subshell that leads its own
+/// process group, and adds it to the job table.
+fn start_job(shell: &mut Shell, mut pipeline: Pipeline) -> Result<i32, ShellError> {
+    pipeline.background = false;
+    let command = pipeline.to_string();
+
+    io::stdout().flush()?;
+    let pid = unsafe { libc::fork() };
+    if pid == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    if pid == 0 {
+        unsafe { libc::setpgid(0, 0) };
+        reset_signals(&shell.restored_signals());
+        if !shell.interactive {
+            // A job of a script must not read the script's input.
+            if let Ok(null) = std::fs::File::open("/dev/null") {
+                unsafe { libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO) };
+            }
         }
+        shell.interactive = false;
+        shell.terminal = None;
+        shell.jobs.clear();
+        shell.traps.reset_for_subshell();
+
+        // A lone command replaces the subshell, so that `$!` is its own pid.
+        let replace = !pipeline.negated && pipeline.time.is_none();
+        let status = execute_foreground(shell, pipeline, replace).unwrap_or_else(|e| {
+            Shell::report_error(&e);
+            e.status()
+        });
+        let _ = io::stdout().flush();
+        unsafe { libc::_exit(status) };
     }
 
-    let mut previous_stdout: Option<Stdio> = None;
-    let mut children = vec![];
-    let num_commands = pipeline.commands.len();
+    // Set the group from both sides, so that it exists whichever runs first.
+    unsafe { libc::setpgid(pid, pid) };
+    shell.last_background_pid = Some(pid);
+    let id = shell.jobs.add(pid, vec![Process::running(pid)], command);
+    if shell.interactive {
+        eprintln!("[{}] {}", id, pid);
+    }
+    Ok(0)
+}
 
-    for (i, command) in pipeline.commands.iter().enumerate() {
-        let is_first = i == 0;
-        let is_last = i == num_commands - 1;
+/// Records every stage's status in `PIPESTATUS` and returns the status of
+/// the pipeline as a whole.
+fn pipeline_status(shell: &mut Shell, statuses: Vec<i32>) -> i32 {
+    let last = statuses.last().copied().unwrap_or(0);
+    let status = if shell.pipefail {
+        statuses
+            .iter()
+            .rev()
+            .find(|&&status| status != 0)
+            .copied()
+            .unwrap_or(0)
+    } else {
+        last
+    };
+    shell.arrays.insert(
+        "PIPESTATUS".to_string(),
+        statuses.iter().map(ToString::to_string).collect(),
+    );
+    status
+}
+
+/// Runs the pipeline's commands and returns the status of each stage.
+fn run_pipeline(
+    shell: &mut Shell,
+    pipeline: &Pipeline,
+    substitutions: &mut Substitutions,
+    replace: bool,
+) -> Result<Vec<i32>, ShellError> {
+    let mut expanded = pipeline
+        .commands
+        .iter()
+        .map(|command| {
+            let mut expanded = expand_command(shell, substitutions, command)?;
+            unwrap_prefixes(&mut expanded)?;
+            Ok(expanded)
+        })
+        .collect::<Vec<Result<ExpandedCommand, ShellError>>>();
 
-        let stdin = if is_first {
-            if let Some(input_file) = &command.input {
-                let file = std::fs::File::open(input_file)?;
-                Stdio::from(file)
-            } else {
-                Stdio::inherit()
+    // An unset parameter under `set -u` fails the whole pipeline.
+    if let Some(index) = expanded
+        .iter()
+        .position(|result| matches!(result, Err(ShellError::Unbound(_))))
+        && let Err(e) = expanded.swap_remove(index)
+    {
+        return Err(e);
+    }
+    if shell.xtrace {
+        for expanded_command in expanded.iter().flatten() {
+            trace_command(shell, expanded_command);
+        }
+    }
+
+    if let [Ok(command)] = expanded.as_slice() {
+        let status = if let Some(expression) = &command.conditional {
+            Some(execute_conditional(shell, expression)?)
+        } else if command.program.is_empty() && command.arguments.is_empty() {
+            for (name, value) in &command.assignments {
+                shell.set_var(name, value.clone());
             }
+            Some(open_redirections_only(command))
+        } else if is_builtin(&command.program) {
+            Some(with_assignments(shell, &command.assignments, |shell| {
+                execute_builtin(shell, command)
+            })?)
         } else {
-            previous_stdout.take().unwrap_or(Stdio::inherit())
+            None
         };
+        if let Some(status) = status {
+            return Ok(vec![status]);
+        }
+    }
+
+    let mut previous_stdout: Option<OwnedFd> = None;
+    let mut stages = vec![];
+    let mut group = shell.terminal.as_ref().map(|terminal| Group {
+        pgid: 0,
+        terminal: terminal.fd(),
+    });
+    let num_commands = expanded.len();
 
-        let mut stdout = if !is_last {
-            Stdio::piped()
+    // A stage that cannot start is reported and given a failure status; the
+    // rest of the pipeline still runs.
+    for (i, expanded_command) in expanded.into_iter().enumerate() {
+        let is_last = i == num_commands - 1;
+
+        let stdin = previous_stdout.take();
+        let (next_stdin, stdout): (Option<OwnedFd>, Option<OwnedFd>) = if is_last {
+            (None, None)
         } else {
-            Stdio::inherit()
+            let (reader, writer) = io::pipe()?;
+            (Some(reader.into()), Some(writer.into()))
         };
-        let mut stderr = Stdio::inherit();
-
-        for redir in &command.outputs {
-            let file = std::fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .append(redir.append)
-                .truncate(!redir.append)
-                .open(&redir.path)?;
-
-            if redir.fd == 1 {
-                stdout = Stdio::from(file);
-            } else if redir.fd == 2 {
-                stderr = Stdio::from(file);
+
+        let stage = expanded_command.and_then(|expanded_command| {
+            let replace = replace && num_commands == 1;
+            start_stage(
+                shell,
+                &expanded_command,
+                stdin,
+                stdout,
+                next_stdin.as_ref(),
+                replace,
+                group,
+            )
+        });
+        let stage = stage.unwrap_or_else(|e| {
+            Shell::report_error(&e);
+            Stage::Finished(e.status())
+        });
+        if let (Some(group), Some(pid)) = (&mut group, stage.pid()) {
+            if group.pgid == 0 {
+                group.pgid = pid;
             }
+            // Set from both sides, so the group exists whichever runs first.
+            unsafe { libc::setpgid(pid, group.pgid) };
+        }
+        stages.push(stage);
+        previous_stdout = next_stdin;
+    }
+
+    match group {
+        Some(group) if group.pgid != 0 => {
+            let processes = stages.into_iter().map(Stage::into_process).collect();
+            let id = shell.jobs.add(group.pgid, processes, pipeline.to_string());
+            Ok(foreground_job(shell, id))
+        }
+        _ => stages.into_iter().map(Stage::wait).collect(),
+    }
+}
+
+/// Gives a job the terminal, continues it if it was stopped, and waits until
+/// it finishes or stops. Returns the status of each of its processes, or
+/// 128+N if it was stopped by signal N, in which case it stays in the job
+/// table.
+pub fn foreground_job(shell: &mut Shell, id: usize) -> Vec<i32> {
+    let Some(job) = shell.jobs.get_mut(id) else {
+        return vec![127];
+    };
+    if let Some(terminal) = &shell.terminal {
+        terminal.give_to(job.pgid, job.modes.as_ref());
+    }
+    if let JobState::Stopped(_) = job.state {
+        unsafe { libc::killpg(job.pgid, libc::SIGCONT) };
+        job.state = JobState::Running;
+    }
+
+    let state = shell.jobs.wait(id);
+    let modes = shell.terminal.as_ref().map(Terminal::reclaim);
+    match state {
+        JobState::Stopped(signal) => {
+            shell.jobs.make_current(id);
+            shell.jobs.get_mut(id).unwrap().modes = modes;
+            let job = shell.jobs.get(id).unwrap();
+            eprintln!("\n{}", shell.jobs.format(job, false));
+            vec![128 + signal]
+        }
+        _ => {
+            let Some(job) = shell.jobs.remove(id) else {
+                return vec![0];
+            };
+            for message in job.processes.iter().filter_map(|p| p.termination.as_ref()) {
+                eprintln!("{}", message);
+            }
+            job.statuses()
+        }
+    }
+}
+
+/// The process group a foreground pipeline's processes join when the shell
+/// has job control. The first process to start leads it.
+#[derive(Clone, Copy)]
+struct Group {
+    /// 0 until the first process has started.
+    pgid: libc::pid_t,
+    terminal: RawFd,
+}
+
+impl Group {
+    /// Joins the group and puts it in the terminal's foreground. Runs in the
+    /// child, so it must only make async-signal-safe calls.
+    fn join(self) {
+        unsafe {
+            libc::setpgid(0, self.pgid);
+            libc::tcsetpgrp(self.terminal, libc::getpgrp());
+        }
+    }
+}
+
+/// Prints a command the way `set -x` shows it: `$PS4`, then its expanded
+/// words, after a line for each of its assignments.
+fn trace_command(shell: &Shell, command: &ExpandedCommand) {
+    let ps4 = shell.get_var("PS4").unwrap_or_else(|| "+ ".to_string());
+    for (name, value) in &command.assignments {
+        eprintln!("{}{}={}", ps4, name, quote(value));
+    }
+    let text = match &command.conditional {
+        Some(expression) => format!("[[ {} ]]", expression),
+        None if command.program.is_empty() && command.arguments.is_empty() => return,
+        None => std::iter::once(&command.program)
+            .chain(&command.arguments)
+            .map(|word| quote(word))
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+    eprintln!("{}{}", ps4, text);
+}
+
+/// Runs a command that has redirections but no words, which only creates or
+/// checks the files named.
+fn open_redirections_only(command: &ExpandedCommand) -> i32 {
+    match open_redirections(&command.redirections) {
+        Ok(_) => 0,
+        Err(e) => {
+            Shell::report_error(&e);
+            e.status()
+        }
+    }
+}
+
+/// Starts one stage of a multi-command pipeline with its standard input and
+/// output connected to the neighbouring pipes. The command's own
+/// redirections are applied on top of those. With `replace`, an external
+/// command is exec'd in place of the current process.
+fn start_stage(
+    shell: &mut Shell,
+    command: &ExpandedCommand,
+    stdin: Option<OwnedFd>,
+    stdout: Option<OwnedFd>,
+    parent_only: Option<&OwnedFd>,
+    replace: bool,
+    group: Option<Group>,
+) -> Result<Stage, ShellError> {
+    if command.conditional.is_some() || is_builtin(&command.program) {
+        let pid = fork_stage(shell, command, stdin, stdout, parent_only, group)?;
+        return Ok(Stage::Forked(pid));
+    }
+    if command.program.is_empty() && command.arguments.is_empty() {
+        return Ok(Stage::Finished(open_redirections_only(command)));
+    }
+
+    // The command is looked up with its own assignments, such as `PATH=`.
+    let assignments = &command.assignments;
+    let path = with_assignments(shell, assignments, |shell| {
+        shell.resolve_command(&command.program)
+    })
+    .ok_or_else(|| {
+        ShellError::CommandNotFound(format!("{}: command not found", command.program))
+    })?;
+    let redirections = open_redirections(&command.redirections)?;
+    let inherited_fds = command.inherited_fds.clone();
+    let restored = shell.restored_signals();
+    let mut process = Command::new(path);
+    process.arg0(&command.program);
+    unsafe {
+        process.pre_exec(move || {
+            if let Some(group) = group {
+                group.join();
+            }
+            reset_signals(&restored);
+            for &fd in &inherited_fds {
+                if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            apply_redirections(&redirections)
+        });
+    }
+
+    process
+        .args(&command.arguments)
+        .envs(shell.exported_vars())
+        .envs(assignments.iter().map(|(name, value)| (name, value)))
+        .stdin(stdin.map_or_else(Stdio::inherit, Stdio::from))
+        .stdout(stdout.map_or_else(Stdio::inherit, Stdio::from));
+    if replace {
+        return Err(spawn_error(&command.program, process.exec()));
+    }
+    let child = process
+        .spawn()
+        .map_err(|e| spawn_error(&command.program, e))?;
+    Ok(Stage::Spawned(child))
+}
+
+fn spawn_error(program: &str, error: io::Error) -> ShellError {
+    match error.kind() {
+        io::ErrorKind::NotFound => {
+            ShellError::CommandNotFound(format!("{}: command not found", program))
+        }
+        io::ErrorKind::PermissionDenied => {
+            ShellError::PermissionDenied(format!("{}: Permission denied", program))
         }
+        _ => error.into(),
+    }
+}
 
-        let mut child = Command::new(&command.program)
-            .args(&command.arguments)
-            .stdin(stdin)
-            .stdout(stdout)
-            .stderr(stderr)
-            .spawn()
-            .map_err(|e| {
-                if e.kind() == std::io::ErrorKind::NotFound {
-                    ShellError::CommandNotFound(format!("{}: command not found", command.program))
-                } else {
-                    e.into()
+/// A pipeline stage: an external program, a builtin or `[[ ]]` running in a
+/// forked copy of the shell, or one that has already finished.
+enum Stage {
+    Spawned(Child),
+    Forked(libc::pid_t),
+    Finished(i32),
+}
+
+impl Stage {
+    fn pid(&self) -> Option<libc::pid_t> {
+        match self {
+            Stage::Spawned(child) => Some(child.id() as libc::pid_t),
+            Stage::Forked(pid) => Some(*pid),
+            Stage::Finished(_) => None,
+        }
+    }
+
+    fn into_process(self) -> Process {
+        match self {
+            Stage::Spawned(child) => Process::running(child.id() as libc::pid_t),
+            Stage::Forked(pid) => Process::running(pid),
+            Stage::Finished(status) => Process::finished(status),
+        }
+    }
+
+    /// Waits for the stage, reporting it if a signal ended it. A process
+    /// killed by signal N has status 128+N.
+    fn wait(self) -> Result<i32, ShellError> {
+        let status = match self {
+            Stage::Spawned(mut child) => child.wait()?,
+            Stage::Finished(status) => return Ok(status),
+            Stage::Forked(pid) => {
+                let mut status = 0;
+                if unsafe { libc::waitpid(pid, &mut status, 0) } == -1 {
+                    return Err(io::Error::last_os_error().into());
                 }
-            })?;
+                ExitStatus::from_raw(status)
+            }
+        };
+        if let Some(message) = termination_message(&status) {
+            eprintln!("{}", message);
+        }
+        Ok(status
+            .code()
+            .or_else(|| status.signal().map(|signal| 128 + signal))
+            .unwrap_or(0))
+    }
+}
 
-        if let Some(stdout_pipe) = child.stdout.take() {
-            previous_stdout = Some(Stdio::from(stdout_pipe));
+/// Runs a builtin or `[[ ]]` in a child process with its standard input and
+/// output connected to the neighbouring stages, as if it were a program.
+/// `parent_only` is the shell's end of the next pipe, which the child closes.
+fn fork_stage(
+    shell: &mut Shell,
+    command: &ExpandedCommand,
+    stdin: Option<OwnedFd>,
+    stdout: Option<OwnedFd>,
+    parent_only: Option<&OwnedFd>,
+    group: Option<Group>,
+) -> Result<libc::pid_t, ShellError> {
+    io::stdout().flush()?;
+    let pid = unsafe { libc::fork() };
+    if pid == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    if pid != 0 {
+        return Ok(pid);
+    }
+
+    if let Some(group) = group {
+        group.join();
+    }
+    reset_signals(&shell.restored_signals());
+    unsafe {
+        if let Some(fd) = parent_only {
+            libc::close(fd.as_raw_fd());
         }
+        if let Some(fd) = &stdin {
+            libc::dup2(fd.as_raw_fd(), libc::STDIN_FILENO);
+        }
+        if let Some(fd) = &stdout {
+            libc::dup2(fd.as_raw_fd(), libc::STDOUT_FILENO);
+        }
+    }
+    drop((stdin, stdout));
+    shell.traps.reset_for_subshell();
+    for (name, value) in &command.assignments {
+        shell.set_var(name, value.clone());
+    }
 
-        children.push(child);
+    let result = match &command.conditional {
+        Some(expression) => execute_conditional(shell, expression),
+        None => execute_builtin(shell, command),
+    };
+    let status = result.unwrap_or_else(|e| {
+        Shell::report_error(&e);
+        e.status()
+    });
+    let _ = io::stdout().flush();
+    unsafe { libc::_exit(status) }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::{lexer::Token, parse_tokens},
+        shell::{
+            Shell,
+            signals::{exit_status, ignore_interactive_signals},
+        },
+    };
+    use std::{
+        env, fs,
+        panic::{self, AssertUnwindSafe},
+        path::{Path, PathBuf},
+    };
+
+    /// Runs the pipelines of `source` and returns `$?` afterwards.
+    fn run(shell: &mut Shell, source: &str) -> i32 {
+        shell.execute_pipelines(parse_tokens(Token::tokenize(source).unwrap()).unwrap());
+        shell.last_status
+    }
+
+    /// An empty directory for the files of one test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("shell-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(dir: &Path, name: &str) -> String {
+        fs::read_to_string(dir.join(name)).unwrap()
     }
 
-    let mut last_status = 0;
-    for (i, mut child) in children.into_iter().enumerate() {
-        let status = child.wait()?;
-        if i == num_commands - 1 {
-            last_status = status.code().unwrap_or(0);
+    /// Runs `f` in a forked copy of the test process, so that what it does to
+    /// the process, such as changing its descriptors or signal actions or
+    /// exiting, stays there. Returns the status it exits with.
+    fn in_child(f: impl FnOnce() -> i32) -> i32 {
+        let pid = unsafe { libc::fork() };
+        assert_ne!(pid, -1);
+        if pid == 0 {
+            let status = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(101);
+            unsafe { libc::_exit(status) };
         }
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        exit_status(status)
     }
 
-    Ok(last_status)
+    #[test]
+    fn test_builtin_in_the_middle_of_a_pipeline() {
+        let dir = scratch("middle");
+        let mut shell = Shell::default();
+        let source = format!(
+            "echo one | echo two | cat > {0}/out; printf 'a\\n' | type -t cd | tr a-z A-Z > {0}/type",
+            dir.display()
+        );
+        assert_eq!(run(&mut shell, &source), 0);
+        assert_eq!(read(&dir, "out"), "two\n");
+        assert_eq!(read(&dir, "type"), "BUILTIN\n");
+    }
+
+    #[test]
+    fn test_builtin_writing_into_a_closed_pipe() {
+        let dir = scratch("closed-pipe");
+        let mut shell = Shell::default();
+        // More than a pipe holds, so that the builtin is still writing when
+        // `head` exits.
+        let source = format!(
+            "type{} 2> {1}/err | head -1 > {1}/out",
+            " cd".repeat(20_000),
+            dir.display()
+        );
+        assert_eq!(run(&mut shell, &source), 0);
+        assert_eq!(read(&dir, "out"), "cd is a shell builtin\n");
+        assert_eq!(read(&dir, "err"), "");
+        assert_eq!(shell.arrays["PIPESTATUS"], vec!["141", "0"]);
+    }
+
+    #[test]
+    fn test_redirections_on_builtins() {
+        let dir = scratch("builtin-io");
+        let d = dir.display();
+        fs::write(dir.join("in"), "input\n").unwrap();
+        let mut shell = Shell::default();
+
+        assert_eq!(run(&mut shell, &format!("type nosuch_xyz 2> {}/err", d)), 1);
+        assert_eq!(read(&dir, "err"), "nosuch_xyz: not found\n");
+        assert_eq!(
+            run(&mut shell, &format!("type nosuch_xyz 2> {}/piped | cat", d)),
+            0
+        );
+        assert_eq!(read(&dir, "piped"), "nosuch_xyz: not found\n");
+
+        assert_eq!(
+            run(&mut shell, &format!("echo hi < {0}/in > {0}/out", d)),
+            0
+        );
+        assert_eq!(read(&dir, "out"), "hi\n");
+        assert_eq!(
+            run(&mut shell, &format!("echo hi < {0}/missing > {0}/none", d)),
+            1
+        );
+        assert!(!dir.join("none").exists());
+    }
+
+    #[test]
+    fn test_redirections_apply_left_to_right() {
+        let dir = scratch("order");
+        let d = dir.display();
+        let mut shell = Shell::default();
+
+        assert_eq!(run(&mut shell, &format!("echo hi > {0}/a > {0}/b", d)), 0);
+        assert_eq!(read(&dir, "a"), "");
+        assert_eq!(read(&dir, "b"), "hi\n");
+        assert_eq!(
+            run(&mut shell, &format!("sh -c 'echo x' > {0}/c > {0}/d", d)),
+            0
+        );
+        assert_eq!(read(&dir, "c"), "");
+        assert_eq!(read(&dir, "d"), "x\n");
+
+        // Targets before a failing one are still created; later ones are not.
+        assert_eq!(
+            run(&mut shell, &format!("cat > {0}/e < {0}/missing > {0}/f", d)),
+            1
+        );
+        assert!(dir.join("e").exists());
+        assert!(!dir.join("f").exists());
+    }
+
+    #[test]
+    fn test_failed_redirection_only_fails_its_stage() {
+        let dir = scratch("failed-stage");
+        let d = dir.display();
+        let mut shell = Shell::default();
+
+        let source = format!("cat < {0}/missing | echo ok > {0}/out", d);
+        assert_eq!(run(&mut shell, &source), 0);
+        assert_eq!(read(&dir, "out"), "ok\n");
+        assert_eq!(shell.arrays["PIPESTATUS"], vec!["1", "0"]);
+    }
+
+    #[test]
+    fn test_exit_statuses() {
+        let dir = scratch("statuses");
+        let script = dir.join("not-executable");
+        fs::write(&script, "echo hi\n").unwrap();
+        let mut shell = Shell::default();
+
+        assert_eq!(run(&mut shell, "nosuch_command_xyz"), 127);
+        assert_eq!(run(&mut shell, &script.display().to_string()), 126);
+        assert_eq!(run(&mut shell, "sh -c 'exit 3'"), 3);
+        assert_eq!(
+            run(&mut shell, "sh -c 'kill -TERM $$'"),
+            128 + libc::SIGTERM
+        );
+    }
+
+    #[test]
+    fn test_pipefail_and_pipestatus() {
+        let mut shell = Shell::default();
+        assert_eq!(run(&mut shell, "sh -c 'exit 3' | false | true"), 0);
+        assert_eq!(shell.arrays["PIPESTATUS"], vec!["3", "1", "0"]);
+
+        shell.pipefail = true;
+        assert_eq!(run(&mut shell, "sh -c 'exit 3' | false | true"), 1);
+        assert_eq!(run(&mut shell, "sh -c 'exit 3' | true"), 3);
+        assert_eq!(run(&mut shell, "true | true"), 0);
+        assert_eq!(shell.arrays["PIPESTATUS"], vec!["0", "0"]);
+    }
+
+    #[test]
+    fn test_and_or_lists() {
+        let dir = scratch("and-or");
+        let d = dir.display();
+        let mut shell = Shell::default();
+
+        let source = format!(
+            "true && echo a > {0}/and; false && echo b > {0}/skipped; false || echo c > {0}/or",
+            d
+        );
+        assert_eq!(run(&mut shell, &source), 0);
+        assert_eq!(read(&dir, "and"), "a\n");
+        assert!(!dir.join("skipped").exists());
+        assert_eq!(read(&dir, "or"), "c\n");
+
+        // A skipped pipeline leaves the status alone.
+        assert_eq!(run(&mut shell, "false && true"), 1);
+        assert_eq!(run(&mut shell, "false && true || sh -c 'exit 3'"), 3);
+        assert_eq!(run(&mut shell, "true || false && sh -c 'exit 4'"), 4);
+    }
+
+    #[test]
+    fn test_errexit_skips_tested_failures() {
+        let dir = scratch("errexit");
+        let source = format!(
+            "set -e; false && true; false || true; ! true; echo reached > {0}/reached; \
+             true && false; echo after > {0}/after",
+            dir.display()
+        );
+        assert_eq!(in_child(|| run(&mut Shell::default(), &source)), 1);
+        assert_eq!(read(&dir, "reached"), "reached\n");
+        assert!(!dir.join("after").exists());
+
+        let source = "set -e; false || false; exit 0";
+        assert_eq!(in_child(|| run(&mut Shell::default(), source)), 1);
+    }
+
+    #[test]
+    fn test_children_keep_signals_ignored_by_traps() {
+        let dir = scratch("trap-ignored");
+        let source = format!(
+            "trap '' INT; grep SigIgn /proc/self/status > {}/status",
+            dir.display()
+        );
+        let status = in_child(|| {
+            let mut shell = Shell::default();
+            shell.interactive = true;
+            ignore_interactive_signals();
+            run(&mut shell, &source)
+        });
+        assert_eq!(status, 0);
+
+        let status = read(&dir, "status");
+        let mask = status.trim().strip_prefix("SigIgn:").unwrap().trim();
+        let mask = u64::from_str_radix(mask, 16).unwrap();
+        let ignored = |signal: i32| mask & 1 << (signal - 1) != 0;
+        assert!(ignored(libc::SIGINT));
+        assert!(!ignored(libc::SIGQUIT));
+        assert!(!ignored(libc::SIGTSTP));
+        assert!(!ignored(libc::SIGPIPE));
+    }
+
+    #[test]
+    fn test_exec_redirections_are_permanent() {
+        let dir = scratch("exec");
+        let d = dir.display();
+
+        let source = format!(
+            "exec 9> {0}/out; sh -c 'echo one >&9'; sh -c 'echo two >&9'; \
+             exec nosuch_command_xyz 2> {0}/err; echo after > {0}/after",
+            d
+        );
+        assert_eq!(in_child(|| run(&mut Shell::default(), &source)), 127);
+        assert_eq!(read(&dir, "out"), "one\ntwo\n");
+        assert_eq!(read(&dir, "err"), "exec: nosuch_command_xyz: not found\n");
+        assert!(!dir.join("after").exists());
+
+        let source = format!("exec -z 2> {}/invalid", d);
+        assert_eq!(in_child(|| run(&mut Shell::default(), &source)), 1);
+        assert!(read(&dir, "invalid").starts_with("exec: -z: invalid option\n"));
+    }
 }
@@ -0,0 +1,68 @@
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+};
+
+use crate::{
+    error::ShellError,
+    parser::ast::{Redirection, RedirectionKind},
+};
+
+/// The lowest descriptor redirection targets are opened on, so that moving
+/// one into place never overwrites a target that has not been moved yet.
+const FIRST_SAVED_FD: RawFd = 10;
+
+/// Opens the targets of `redirections` in source order, pairing each with the
+/// descriptor it replaces. Fails with `path: reason` on the first target that
+/// cannot be opened.
+pub fn open_redirections(
+    redirections: &[Redirection],
+) -> Result<Vec<(RawFd, OwnedFd)>, ShellError> {
+    redirections
+        .iter()
+        .map(|redirection| Ok((redirection.fd, open_target(redirection)?)))
+        .collect()
+}
+
+fn open_target(redirection: &Redirection) -> Result<OwnedFd, ShellError> {
+    let file = match redirection.kind {
+        RedirectionKind::Input => File::open(&redirection.path),
+        RedirectionKind::Output => OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&redirection.path),
+        RedirectionKind::Append => OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&redirection.path),
+    }
+    .map_err(|e| ShellError::InternalError(format!("{}: {}", redirection.path, describe(&e))))?;
+
+    let fd = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_DUPFD_CLOEXEC, FIRST_SAVED_FD) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Moves opened targets onto the descriptors they redirect, in order. Runs in
+/// a child process before it execs or runs a builtin.
+pub fn apply_redirections(opened: &[(RawFd, OwnedFd)]) -> io::Result<()> {
+    for (target, fd) in opened {
+        if unsafe { libc::dup2(fd.as_raw_fd(), *target) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// An I/O error's description without the `(os error N)` suffix.
+pub fn describe(error: &io::Error) -> String {
+    let text = error.to_string();
+    match text.find(" (os error") {
+        Some(end) => text[..end].to_string(),
+        None => text,
+    }
+}
@@ -0,0 +1,81 @@
+use std::{
+    io::{self, Write},
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+};
+
+use crate::{
+    error::ShellError,
+    parser::ast::{ProcessSubstitution, ProcessSubstitutionKind},
+    shell::{Shell, signals::reset_signals},
+};
+
+/// The process substitutions started while expanding a pipeline. The shell
+/// keeps its end of every pipe open until the pipeline has finished, then
+/// closes them and reaps the inner processes.
+#[derive(Default)]
+pub struct Substitutions {
+    fds: Vec<OwnedFd>,
+    pids: Vec<libc::pid_t>,
+}
+
+impl Substitutions {
+    /// Runs the substitution's list in a subshell connected to a new pipe and
+    /// returns the shell's end of it.
+    pub fn start(
+        &mut self,
+        shell: &mut Shell,
+        substitution: &ProcessSubstitution,
+    ) -> Result<RawFd, ShellError> {
+        let (reader, writer) = io::pipe()?;
+        let (ours, theirs): (OwnedFd, OwnedFd) = match substitution.kind {
+            ProcessSubstitutionKind::Input => (reader.into(), writer.into()),
+            ProcessSubstitutionKind::Output => (writer.into(), reader.into()),
+        };
+        let target = match substitution.kind {
+            ProcessSubstitutionKind::Input => libc::STDOUT_FILENO,
+            ProcessSubstitutionKind::Output => libc::STDIN_FILENO,
+        };
+
+        io::stdout().flush()?;
+        let pid = unsafe { libc::fork() };
+        if pid == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        if pid == 0 {
+            // The subshell must not hold on to any other pipe the shell has
+            // open, or the processes on the far side would never see EOF.
+            unsafe {
+                libc::close(ours.as_raw_fd());
+                for fd in &self.fds {
+                    libc::close(fd.as_raw_fd());
+                }
+                libc::dup2(theirs.as_raw_fd(), target);
+                libc::close(theirs.as_raw_fd());
+            }
+
+            reset_signals(&shell.restored_signals());
+            shell.interactive = false;
+            shell.terminal = None;
+            shell.jobs.clear();
+            shell.traps.reset_for_subshell();
+            shell.execute_pipelines(substitution.pipelines.clone());
+            let _ = io::stdout().flush();
+            unsafe { libc::_exit(shell.last_status) };
+        }
+
+        let fd = ours.as_raw_fd();
+        self.fds.push(ours);
+        self.pids.push(pid);
+        Ok(fd)
+    }
+
+    /// Closes the shell's ends of the pipes and waits for every inner list.
+    pub fn reap(self) {
+        drop(self.fds);
+        for pid in self.pids {
+            let mut status = 0;
+            unsafe { libc::waitpid(pid, &mut status, 0) };
+        }
+    }
+}
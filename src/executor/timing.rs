@@ -0,0 +1,151 @@
+use std::time::{Duration, Instant};
+
+use crate::parser::ast::TimeFormat;
+
+const DEFAULT_TIMEFORMAT: &str = "\nreal\t%3lR\nuser\t%3lU\nsys\t%3lS";
+const POSIX_TIMEFORMAT: &str = "real %2R\nuser %2U\nsys %2S";
+
+/// Wall-clock and CPU time consumed by the shell and its waited-for children.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Times {
+    pub real: Duration,
+    pub user: Duration,
+    pub sys: Duration,
+}
+
+/// Measures a timed pipeline from the moment it is created.
+pub struct Stopwatch {
+    started: Instant,
+    user: Duration,
+    sys: Duration,
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        let (user, sys) = cpu_times();
+        Stopwatch {
+            started: Instant::now(),
+            user,
+            sys,
+        }
+    }
+
+    pub fn stop(&self) -> Times {
+        let (user, sys) = cpu_times();
+        Times {
+            real: self.started.elapsed(),
+            user: user.saturating_sub(self.user),
+            sys: sys.saturating_sub(self.sys),
+        }
+    }
+}
+
+fn cpu_times() -> (Duration, Duration) {
+    let mut user = Duration::ZERO;
+    let mut sys = Duration::ZERO;
+
+    for who in [libc::RUSAGE_SELF, libc::RUSAGE_CHILDREN] {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(who, &mut usage) } == 0 {
+            user += timeval_to_duration(usage.ru_utime);
+            sys += timeval_to_duration(usage.ru_stime);
+        }
+    }
+
+    (user, sys)
+}
+
+fn timeval_to_duration(tv: libc::timeval) -> Duration {
+    Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+}
+
+/// Renders `times` the way bash does for the given `time` variant.
+/// `timeformat` is the value of `$TIMEFORMAT`, if it is set.
+pub fn report(format: TimeFormat, timeformat: Option<&str>, times: &Times) -> String {
+    let template = match format {
+        TimeFormat::Posix => POSIX_TIMEFORMAT,
+        TimeFormat::Variable => timeformat.unwrap_or(DEFAULT_TIMEFORMAT),
+    };
+
+    if template.is_empty() {
+        return String::new();
+    }
+
+    let mut output = format_times(template, times);
+    output.push('\n');
+    output
+}
+
+/// Expands the `%[p][l]R`, `%[p][l]U`, `%[p][l]S`, `%P` and `%%` escapes of
+/// a `TIMEFORMAT` string.
+pub fn format_times(template: &str, times: &Times) -> String {
+    let mut output = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+
+        let mut precision = 3;
+        let mut long = false;
+        if let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            precision = digit.min(3) as usize;
+            chars.next();
+        }
+        if chars.peek() == Some(&'l') {
+            long = true;
+            chars.next();
+        }
+
+        match chars.next() {
+            Some('%') => output.push('%'),
+            Some('R') => output.push_str(&format_duration(times.real, precision, long)),
+            Some('U') => output.push_str(&format_duration(times.user, precision, long)),
+            Some('S') => output.push_str(&format_duration(times.sys, precision, long)),
+            Some('P') => {
+                let real = times.real.as_secs_f64();
+                let cpu = (times.user + times.sys).as_secs_f64();
+                let percent = if real > 0.0 { cpu * 100.0 / real } else { 0.0 };
+                output.push_str(&format!("{:.2}", percent));
+            }
+            Some(other) => {
+                output.push('%');
+                output.push(other);
+            }
+            None => output.push('%'),
+        }
+    }
+
+    output
+}
+
+fn format_duration(duration: Duration, precision: usize, long: bool) -> String {
+    let seconds = duration.as_secs_f64();
+    if long {
+        let minutes = (seconds / 60.0).floor();
+        format!("{}m{:.*}s", minutes, precision, seconds - minutes * 60.0)
+    } else {
+        format!("{:.*}", precision, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Times, format_times};
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_times() {
+        let times = Times {
+            real: Duration::from_millis(61_500),
+            user: Duration::from_millis(250),
+            sys: Duration::from_millis(5),
+        };
+        assert_eq!(
+            format_times("real\t%3lR user %2U sys %1S %%", &times),
+            "real\t1m1.500s user 0.25 sys 0.0 %"
+        );
+    }
+}
@@ -1,13 +1,179 @@
 use codecrafters_shell::{error::ShellError, shell::Shell};
 use rustyline::Config;
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    process,
+};
+
+/// What `--dump-tokens` and `--dump-ast` print instead of running the input.
+#[derive(Clone, Copy)]
+enum Dump {
+    Tokens,
+    Ast,
+}
 
 fn main() -> Result<(), ShellError> {
+    let program = env::args().next().unwrap_or_default();
+    let mut dump = None;
+    let mut posix = false;
+    let mut command = None;
+    let mut operands = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dump-tokens" => dump = Some(Dump::Tokens),
+            "--dump-ast" => dump = Some(Dump::Ast),
+            "--posix" => posix = true,
+            "-c" => match args.next() {
+                Some(string) => command = Some(string),
+                None => usage(&program, "-c: option requires an argument"),
+            },
+            _ if arg.starts_with('-') && arg != "-" => {
+                usage(&program, &format!("{}: invalid option", arg))
+            }
+            _ => {
+                operands.push(arg);
+                operands.extend(args.by_ref());
+            }
+        }
+    }
+
+    // With `-c`, the operands are `$0` and the positional parameters; without
+    // it, the first operand is the script to run.
+    let mut operands = operands.into_iter();
+    let source = match command {
+        Some(string) => Some(string),
+        None => operands.next().map(|script| {
+            fs::read_to_string(&script).unwrap_or_else(|e| {
+                eprintln!("{}: {}: {}", program, script, e);
+                process::exit(127);
+            })
+        }),
+    };
+
+    if let Some(dump) = dump {
+        let source = source.unwrap_or_else(|| {
+            let mut input = String::new();
+            if let Err(e) = io::stdin().read_to_string(&mut input) {
+                eprintln!("{}: {}", program, e);
+                process::exit(1);
+            }
+            input
+        });
+        process::exit(dump_source(dump, &source, &mut io::stdout()));
+    }
+
     let config = Config::builder()
         .completion_type(rustyline::CompletionType::List)
         .build();
 
     let mut shell = Shell::new(config);
-    shell.run();
+    shell.posix = posix;
+    match source {
+        Some(source) => {
+            if let Some(name) = operands.next() {
+                shell.shell_name = name;
+            }
+            shell.positional_params = operands.collect();
+            shell.run_source(&source);
+        }
+        None => shell.run(),
+    }
+
+    let status = shell.last_status;
+    shell.exit(status);
+}
+
+fn usage(program: &str, message: &str) -> ! {
+    eprintln!("{}: {}", program, message);
+    eprintln!(
+        "usage: {} [--posix] [--dump-tokens | --dump-ast] [-c string | script] [args...]",
+        program
+    );
+    process::exit(2);
+}
+
+/// Prints the tokens of `source`, or the syntax tree of each command in it,
+/// as JSON documents one per line. Commands may span lines, as they do when
+/// the shell runs `source`. Returns 2 if the input fails to tokenize or a
+/// command fails to parse.
+#[cfg(feature = "serde")]
+fn dump_source(dump: Dump, source: &str, out: &mut impl Write) -> i32 {
+    use codecrafters_shell::parser::incremental::{ParseStatus, Parser};
+
+    let mut print = |json: serde_json::Result<String>| {
+        let json = json.expect("tokens and syntax trees serialize");
+        let _ = writeln!(out, "{}", json);
+    };
+    match dump {
+        Dump::Tokens => match Parser::tokenize(source) {
+            Ok(tokens) => {
+                print(serde_json::to_string(&tokens));
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                2
+            }
+        },
+        Dump::Ast => {
+            let mut parser = Parser::new();
+            let mut status = 0;
+            let mut dump_status = |parsed: ParseStatus| match parsed {
+                ParseStatus::Complete(pipelines) if !pipelines.is_empty() => {
+                    print(serde_json::to_string(&pipelines));
+                }
+                ParseStatus::Complete(_) | ParseStatus::NeedMore(_) => {}
+                ParseStatus::Error(_, message) => {
+                    eprintln!("{}", ShellError::ParseError(message));
+                    status = 2;
+                }
+            };
+            for line in source.split_inclusive('\n') {
+                dump_status(parser.feed(line));
+            }
+            dump_status(parser.finish());
+            status
+        }
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn dump_source(_dump: Dump, _source: &str, _out: &mut impl Write) -> i32 {
+    eprintln!("--dump-tokens and --dump-ast need the shell built with the `serde` feature");
+    2
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::{Dump, dump_source};
+
+    fn dump(dump: Dump, source: &str) -> (i32, String) {
+        let mut out = Vec::new();
+        let status = dump_source(dump, source, &mut out);
+        (status, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_dump_follows_commands_across_lines() {
+        let (status, ast) = dump(Dump::Ast, "echo a |\ncat\necho 'b\nc'\n");
+        assert_eq!(status, 0);
+        let documents: Vec<&str> = ast.lines().collect();
+        assert_eq!(documents.len(), 2);
+        assert!(documents[0].contains(r#""program":"cat""#));
+        assert!(documents[1].contains(r#""b\nc""#));
+
+        let (status, tokens) = dump(Dump::Tokens, "echo a\necho b");
+        assert_eq!(status, 0);
+        assert_eq!(
+            tokens,
+            r#"[{"Word":"echo"},{"Word":"a"},"Semicolon",{"Word":"echo"},{"Word":"b"}]"#
+                .to_string()
+                + "\n"
+        );
 
-    Ok(())
+        assert_eq!(dump(Dump::Ast, "echo 'a\n").0, 2);
+    }
 }
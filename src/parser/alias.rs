@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+
+use crate::{error::ShellError, parser::lexer::Token};
+
+/// Replaces the first word of every simple command with the value of the
+/// alias of that name, if there is one. A word with any quoting, such as
+/// `\ls` or `'ls'`, is never expanded. An alias is not expanded again inside
+/// its own value, and an alias whose value ends in a blank makes the word
+/// after it eligible for expansion too.
+pub fn expand_aliases(
+    tokens: Vec<Token>,
+    aliases: &BTreeMap<String, String>,
+) -> Result<Vec<Token>, ShellError> {
+    let mut expanded = Vec::new();
+    expand_into(tokens, aliases, &[], true, &mut expanded)?;
+    Ok(expanded)
+}
+
+/// Expands `tokens` onto the end of `expanded`, with `check_next` saying
+/// whether the first word is in command position. Returns whether a word
+/// following these tokens would be.
+fn expand_into(
+    tokens: Vec<Token>,
+    aliases: &BTreeMap<String, String>,
+    active: &[&str],
+    mut check_next: bool,
+    expanded: &mut Vec<Token>,
+) -> Result<bool, ShellError> {
+    let mut tokens = tokens.into_iter();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) if check_next => {
+                if let Some((name, value)) = aliases.get_key_value(&word)
+                    && !active.contains(&name.as_str())
+                {
+                    let mut nested = active.to_vec();
+                    nested.push(name);
+                    let inner =
+                        expand_into(Token::tokenize(value)?, aliases, &nested, true, expanded)?;
+                    check_next = inner || value.ends_with([' ', '\t']);
+                } else {
+                    // Reserved words that prefix a pipeline leave the
+                    // following word in command position.
+                    check_next = word == "!" || word == "time";
+                    expanded.push(Token::Word(word));
+                }
+            }
+            Token::QuotedWord(_) if check_next => {
+                // Quoting a command word is how an alias is bypassed.
+                expanded.push(token);
+                check_next = false;
+            }
+            // The command name still follows assignments.
+            Token::Assignment(..) => expanded.push(token),
+            Token::Pipe | Token::AndIf | Token::OrIf | Token::Semicolon | Token::Background => {
+                expanded.push(token);
+                check_next = true;
+            }
+            Token::RedirectOut(_) | Token::RedirectAppend(_) | Token::RedirectIn(_) => {
+                expanded.push(token);
+                expanded.extend(tokens.next());
+            }
+            _ => {
+                expanded.push(token);
+                check_next = false;
+            }
+        }
+    }
+
+    Ok(check_next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand_aliases;
+    use crate::parser::lexer::Token;
+    use std::collections::BTreeMap;
+
+    fn expand(input: &str, aliases: &[(&str, &str)]) -> Vec<Token> {
+        let aliases: BTreeMap<String, String> = aliases
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        expand_aliases(Token::tokenize(input).unwrap(), &aliases).unwrap()
+    }
+
+    #[test]
+    fn test_alias_expands_command_words_only() {
+        let tokens = expand("gs gs | gs", &[("gs", "git status")]);
+        assert_eq!(
+            tokens,
+            Token::tokenize("git status gs | git status").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_alias_is_not_expanded_recursively() {
+        let tokens = expand("ls", &[("ls", "ls -F")]);
+        assert_eq!(tokens, Token::tokenize("ls -F").unwrap());
+    }
+
+    #[test]
+    fn test_quoted_words_are_not_expanded() {
+        let aliases = [("ll", "ls -l")];
+        for input in [r"\ll x", "'ll' x", "\"ll\" x", "l\\l x"] {
+            assert_eq!(expand(input, &aliases), Token::tokenize(input).unwrap());
+        }
+        assert_eq!(
+            expand("ll x", &aliases),
+            Token::tokenize("ls -l x").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_trailing_blank_expands_next_word() {
+        let tokens = expand("sudo ll x", &[("sudo", "sudo "), ("ll", "ls -l")]);
+        assert_eq!(tokens, Token::tokenize("sudo ls -l x").unwrap());
+    }
+
+    #[test]
+    fn test_alias_expands_after_and_or() {
+        let tokens = expand("true && gs || gs", &[("gs", "git status")]);
+        assert_eq!(
+            tokens,
+            Token::tokenize("true && git status || git status").unwrap()
+        );
+    }
+}
@@ -1,19 +1,168 @@
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Redirection {
     pub path: String,
+    /// The target as parsed; `path` is rebuilt from it when the command is
+    /// expanded.
+    pub target: Word,
     pub fd: i32,
-    pub append: bool,
+    pub kind: RedirectionKind,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum RedirectionKind {
+    /// `<`
+    Input,
+    /// `>`
+    Output,
+    /// `>>`
+    Append,
+}
+
+impl RedirectionKind {
+    /// The descriptor redirected when the operator has no number before it.
+    pub fn default_fd(self) -> i32 {
+        match self {
+            RedirectionKind::Input => 0,
+            RedirectionKind::Output | RedirectionKind::Append => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ProcessSubstitutionKind {
+    /// `<(list)`: the command reads what the list writes.
+    Input,
+    /// `>(list)`: the command writes what the list reads.
+    Output,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ProcessSubstitution {
+    pub kind: ProcessSubstitutionKind,
+    pub pipelines: Vec<Pipeline>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum WordPart {
+    Literal(String),
+    /// Quoted text inside `[[ ]]`, which patterns and regular expressions
+    /// match literally. Everywhere else quoted text is folded into `Literal`.
+    Quoted(String),
+    /// `$name` or `${name}`. Unquoted values are subject to field splitting.
+    Parameter {
+        name: String,
+        quoted: bool,
+    },
+    ProcessSubstitution(ProcessSubstitution),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+impl Word {
+    pub fn literal(text: String) -> Self {
+        Word {
+            parts: vec![WordPart::Literal(text)],
+        }
+    }
+
+    /// The word's text with every non-literal part left out.
+    pub fn literal_text(&self) -> String {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                WordPart::Literal(text) | WordPart::Quoted(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The expression inside `[[ ... ]]`. Operators are kept as written, e.g.
+/// `-f`, `==` or `-nt`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ConditionalExpression {
+    /// A lone word, true if it expands to a non-empty string.
+    Word(Word),
+    Unary {
+        operator: String,
+        operand: Word,
+    },
+    Binary {
+        operator: String,
+        left: Word,
+        right: Word,
+    },
+    Not(Box<ConditionalExpression>),
+    And(Box<ConditionalExpression>, Box<ConditionalExpression>),
+    Or(Box<ConditionalExpression>, Box<ConditionalExpression>),
+}
+
+/// A `name=value` word before a command's name.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Assignment {
+    pub name: String,
+    /// Expanded without field splitting. Empty for `name=`.
+    pub value: Word,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Command {
-    pub program: String,
-    pub arguments: Vec<String>,
-    pub input: Option<String>,
-    pub outputs: Vec<Redirection>,
+    /// Without a command name these set shell variables; with one, they are
+    /// set in the command's environment only.
+    pub assignments: Vec<Assignment>,
+    /// The command's words as parsed, program first. Its program name and
+    /// arguments are only known once these are expanded.
+    pub words: Vec<Word>,
+    /// In source order, which is the order they are applied in.
+    pub redirections: Vec<Redirection>,
+    /// Set for a `[[ ... ]]` command, which has no words of its own.
+    pub conditional: Option<ConditionalExpression>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TimeFormat {
+    /// Report using `$TIMEFORMAT`, or bash's default layout when it is unset.
+    Variable,
+    /// `time -p`: the POSIX layout.
+    Posix,
+}
+
+/// How a pipeline in an and-or list depends on the one before it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum AndOr {
+    /// `&&`: runs only if the previous pipeline succeeded.
+    And,
+    /// `||`: runs only if the previous pipeline failed.
+    Or,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Pipeline {
+    /// Set when `&&` or `||` joins the pipeline to the one before it. A
+    /// skipped pipeline leaves `$?` alone, which makes the list
+    /// left-associative.
+    pub and_or: Option<AndOr>,
     pub commands: Vec<Command>,
+    /// `! pipeline`: the exit status is inverted.
+    pub negated: bool,
+    /// `time pipeline`: timing statistics are reported once it finishes.
+    pub time: Option<TimeFormat>,
+    /// `pipeline &`: run as a job without waiting for it. After an and-or
+    /// list, only its last pipeline is.
+    pub background: bool,
 }
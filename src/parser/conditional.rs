@@ -0,0 +1,134 @@
+use std::{iter::Peekable, vec::IntoIter};
+
+use crate::{
+    error::ShellError,
+    parser::{
+        ast::{ConditionalExpression, Word, WordPart},
+        lexer::Token,
+    },
+};
+
+pub const UNARY_OPERATORS: &[&str] = &[
+    "-a", "-b", "-c", "-d", "-e", "-f", "-g", "-h", "-k", "-n", "-p", "-r", "-s", "-t", "-u", "-v",
+    "-w", "-x", "-z", "-G", "-L", "-O", "-S",
+];
+
+pub const BINARY_OPERATORS: &[&str] = &[
+    "=", "==", "!=", "<", ">", "=~", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+/// Parses the tokens after `[[` up to and including the closing `]]`.
+/// `||` binds loosest, then `&&`, then `!`.
+pub fn parse_conditional(
+    tokens_iter: &mut Peekable<IntoIter<Token>>,
+) -> Result<ConditionalExpression, ShellError> {
+    let expression = parse_or(tokens_iter)?;
+    match tokens_iter.next() {
+        Some(Token::ConditionalEnd) => Ok(expression),
+        _ => Err(ShellError::ParseError(
+            "Expected `]]' to close conditional expression".to_string(),
+        )),
+    }
+}
+
+fn parse_or(
+    tokens_iter: &mut Peekable<IntoIter<Token>>,
+) -> Result<ConditionalExpression, ShellError> {
+    let mut expression = parse_and(tokens_iter)?;
+    while tokens_iter.next_if_eq(&Token::Or).is_some() {
+        let right = parse_and(tokens_iter)?;
+        expression = ConditionalExpression::Or(Box::new(expression), Box::new(right));
+    }
+    Ok(expression)
+}
+
+fn parse_and(
+    tokens_iter: &mut Peekable<IntoIter<Token>>,
+) -> Result<ConditionalExpression, ShellError> {
+    let mut expression = parse_not(tokens_iter)?;
+    while tokens_iter.next_if_eq(&Token::And).is_some() {
+        let right = parse_not(tokens_iter)?;
+        expression = ConditionalExpression::And(Box::new(expression), Box::new(right));
+    }
+    Ok(expression)
+}
+
+fn parse_not(
+    tokens_iter: &mut Peekable<IntoIter<Token>>,
+) -> Result<ConditionalExpression, ShellError> {
+    if tokens_iter
+        .next_if(|token| operator(token) == Some("!"))
+        .is_some()
+    {
+        return Ok(ConditionalExpression::Not(Box::new(parse_not(
+            tokens_iter,
+        )?)));
+    }
+    parse_primary(tokens_iter)
+}
+
+fn parse_primary(
+    tokens_iter: &mut Peekable<IntoIter<Token>>,
+) -> Result<ConditionalExpression, ShellError> {
+    if tokens_iter.next_if_eq(&Token::LeftParen).is_some() {
+        let expression = parse_or(tokens_iter)?;
+        if tokens_iter.next_if_eq(&Token::RightParen).is_none() {
+            return Err(ShellError::ParseError(
+                "Expected `)' in conditional expression".to_string(),
+            ));
+        }
+        return Ok(expression);
+    }
+
+    let first = expect_word(tokens_iter)?;
+
+    if let Some(unary) = word_operator(&first).filter(|op| UNARY_OPERATORS.contains(op))
+        && matches!(tokens_iter.peek(), Some(Token::ExpandableWord(_)))
+    {
+        return Ok(ConditionalExpression::Unary {
+            operator: unary.to_string(),
+            operand: expect_word(tokens_iter)?,
+        });
+    }
+
+    if let Some(binary) = tokens_iter
+        .peek()
+        .and_then(operator)
+        .filter(|op| BINARY_OPERATORS.contains(op))
+    {
+        let operator = binary.to_string();
+        tokens_iter.next();
+        let right = expect_word(tokens_iter)?;
+        return Ok(ConditionalExpression::Binary {
+            operator,
+            left: first,
+            right,
+        });
+    }
+
+    Ok(ConditionalExpression::Word(first))
+}
+
+fn expect_word(tokens_iter: &mut Peekable<IntoIter<Token>>) -> Result<Word, ShellError> {
+    match tokens_iter.next() {
+        Some(Token::ExpandableWord(word)) => Ok(word),
+        _ => Err(ShellError::ParseError(
+            "Unexpected token in conditional expression".to_string(),
+        )),
+    }
+}
+
+/// The text of a word that could be an operator: a single unquoted literal.
+pub fn word_operator(word: &Word) -> Option<&str> {
+    match word.parts.as_slice() {
+        [WordPart::Literal(text)] => Some(text),
+        _ => None,
+    }
+}
+
+fn operator(token: &Token) -> Option<&str> {
+    match token {
+        Token::ExpandableWord(word) => word_operator(word),
+        _ => None,
+    }
+}
@@ -0,0 +1,360 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    error::ShellError,
+    parser::{
+        alias::expand_aliases,
+        ast::Pipeline,
+        lexer::Token,
+        parse_tokens,
+        posix::{check_posix, check_posix_source},
+    },
+};
+
+/// What the input ends inside of, when a command is not finished yet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Incomplete {
+    SingleQuote,
+    DoubleQuote,
+    /// A `${` without its `}`.
+    Parameter,
+    ProcessSubstitution,
+    /// A `[[` without its `]]`.
+    Conditional,
+    /// A backslash escaping the final newline.
+    Backslash,
+    /// A `|` with no command after it yet.
+    Pipe,
+    /// A `&&` or `||` with no pipeline after it yet.
+    AndOr,
+    /// A line that has not been ended by a newline.
+    Line,
+}
+
+impl fmt::Display for Incomplete {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Incomplete::SingleQuote => "Unclosed single quote",
+            Incomplete::DoubleQuote => "Unclosed double quote",
+            Incomplete::Parameter => "Unclosed parameter expansion",
+            Incomplete::ProcessSubstitution => "Unclosed process substitution",
+            Incomplete::Conditional => "Unclosed [[ conditional",
+            Incomplete::Backslash => "Line continuation",
+            Incomplete::Pipe => "Expected a command after '|'",
+            Incomplete::AndOr => "Expected a command after '&&' or '||'",
+            Incomplete::Line => "Unterminated line",
+        })
+    }
+}
+
+/// A byte range of the input fed since the parser last returned a result.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseStatus {
+    Complete(Vec<Pipeline>),
+    NeedMore(Incomplete),
+    Error(Span, String),
+}
+
+/// Parses input that arrives in pieces, such as lines typed at a prompt.
+///
+/// Input is lexed a line at a time, and the tokens of finished lines are
+/// kept, so feeding more input only re-lexes the lines of a construct that
+/// is still open. Newlines separate commands, except after `|`, `&&`, `||`
+/// or a backslash. Once a result other than `NeedMore` is returned, the
+/// parser starts over with the next input.
+#[derive(Debug, Default)]
+pub struct Parser {
+    /// Aliases expanded in each command once it is complete.
+    pub aliases: BTreeMap<String, String>,
+    /// Reject bash extensions, as `set -o posix` does.
+    pub posix: bool,
+    input: String,
+    /// Where the lines that have not been lexed yet start in `input`.
+    pending: usize,
+    tokens: Vec<Token>,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Parser::default()
+    }
+
+    /// Whether no input has been fed since the last result.
+    pub fn is_idle(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    pub fn feed(&mut self, chunk: &str) -> ParseStatus {
+        self.input.push_str(chunk);
+
+        // Nothing can be lexed until another line is finished.
+        if !chunk.contains('\n') {
+            return ParseStatus::NeedMore(Incomplete::Line);
+        }
+        let lines_end = self.input.rfind('\n').unwrap() + 1;
+        if let Err(status) = self.lex_pending(lines_end) {
+            return status;
+        }
+        if lines_end < self.input.len() {
+            return ParseStatus::NeedMore(Incomplete::Line);
+        }
+        if let Some(reason) = self.continued() {
+            return ParseStatus::NeedMore(reason);
+        }
+        self.complete()
+    }
+
+    /// Parses whatever has been fed as the end of the input, so constructs
+    /// that are still open are errors.
+    pub fn finish(&mut self) -> ParseStatus {
+        let end = self.input.len();
+        let status = match self.lex_pending(end) {
+            Err(ParseStatus::NeedMore(reason)) => Some(reason),
+            Err(status) => return status,
+            Ok(()) => self.continued(),
+        };
+        match status {
+            Some(reason) => self.fail(self.pending, format!("{} at end of input", reason)),
+            None => self.complete(),
+        }
+    }
+
+    /// Lexes the whole of `source` the way `feed` and `finish` would, with
+    /// the newlines that end commands turned into `;`, without parsing it.
+    pub fn tokenize(source: &str) -> Result<Vec<Token>, ShellError> {
+        let mut parser = Parser::new();
+        parser.input.push_str(source);
+        match parser.lex_pending(source.len()) {
+            Ok(()) => Ok(parser.tokens),
+            Err(ParseStatus::NeedMore(reason)) => Err(ShellError::ParseError(format!(
+                "{} at end of input",
+                reason
+            ))),
+            Err(ParseStatus::Error(_, message)) => Err(ShellError::ParseError(message)),
+            Err(ParseStatus::Complete(_)) => unreachable!("lexing never completes a command"),
+        }
+    }
+
+    /// Why the command goes on past the end of the lexed lines: an operator
+    /// that needs a command after it.
+    fn continued(&self) -> Option<Incomplete> {
+        match self.tokens.last() {
+            Some(Token::Pipe) => Some(Incomplete::Pipe),
+            Some(Token::AndIf | Token::OrIf) => Some(Incomplete::AndOr),
+            _ => None,
+        }
+    }
+
+    /// Lexes the lines in `input[pending..end]` onto `tokens`, a line at a
+    /// time, leaving any lines of a construct that is still open pending.
+    fn lex_pending(&mut self, end: usize) -> Result<(), ParseStatus> {
+        let mut incomplete = None;
+        let mut line_end = self.pending;
+        while line_end < end {
+            line_end = match self.input[line_end..end].find('\n') {
+                Some(newline) => line_end + newline + 1,
+                None => end,
+            };
+            let source = &self.input[self.pending..line_end];
+            if self.posix
+                && let Err((span, e)) = check_posix_source(source)
+            {
+                let start = self.pending;
+                return Err(self.fail_at(
+                    Span {
+                        start: start + span.start,
+                        end: start + span.end,
+                    },
+                    message(e),
+                ));
+            }
+            match Token::tokenize(source) {
+                Ok(tokens) => {
+                    if !tokens.is_empty()
+                        && !matches!(
+                            self.tokens.last(),
+                            None | Some(
+                                Token::Pipe
+                                    | Token::AndIf
+                                    | Token::OrIf
+                                    | Token::Semicolon
+                                    | Token::Background
+                            )
+                        )
+                    {
+                        self.tokens.push(Token::Semicolon);
+                    }
+                    self.tokens.extend(tokens);
+                    self.pending = line_end;
+                    incomplete = None;
+                }
+                Err(ShellError::Incomplete(reason)) => incomplete = Some(reason),
+                Err(e) => return Err(self.fail(self.pending, message(e))),
+            }
+        }
+        match incomplete {
+            Some(reason) => Err(ParseStatus::NeedMore(reason)),
+            None => Ok(()),
+        }
+    }
+
+    fn complete(&mut self) -> ParseStatus {
+        let tokens = std::mem::take(&mut self.tokens);
+        let parsed = expand_aliases(tokens, &self.aliases)
+            .and_then(parse_tokens)
+            .and_then(|pipelines| {
+                if self.posix {
+                    check_posix(&pipelines)?;
+                }
+                Ok(pipelines)
+            });
+        match parsed {
+            Ok(pipelines) => {
+                self.reset();
+                ParseStatus::Complete(pipelines)
+            }
+            Err(e) => self.fail(0, message(e)),
+        }
+    }
+
+    /// Reports an error in the input from `start` to the end.
+    fn fail(&mut self, start: usize, message: String) -> ParseStatus {
+        let end = self.input.len();
+        self.fail_at(Span { start, end }, message)
+    }
+
+    fn fail_at(&mut self, span: Span, message: String) -> ParseStatus {
+        self.reset();
+        ParseStatus::Error(span, message)
+    }
+
+    fn reset(&mut self) {
+        self.input.clear();
+        self.pending = 0;
+        self.tokens.clear();
+    }
+}
+
+fn message(error: ShellError) -> String {
+    match error {
+        ShellError::ParseError(message) => message,
+        ShellError::Incomplete(reason) => reason.to_string(),
+        e => e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Incomplete, ParseStatus, Parser, Span};
+    use crate::parser::{lexer::Token, parse_tokens};
+
+    fn parse(input: &str) -> ParseStatus {
+        ParseStatus::Complete(parse_tokens(Token::tokenize(input).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn test_feeds_until_quote_is_closed() {
+        let mut parser = Parser::new();
+        assert_eq!(
+            parser.feed("echo 'a\n"),
+            ParseStatus::NeedMore(Incomplete::SingleQuote)
+        );
+        assert_eq!(parser.feed("b' c"), ParseStatus::NeedMore(Incomplete::Line));
+        assert_eq!(parser.feed("\n"), parse("echo 'a\nb' c"));
+        assert!(parser.is_idle());
+    }
+
+    #[test]
+    fn test_newlines_separate_commands_except_after_pipe_and_backslash() {
+        let mut parser = Parser::new();
+        assert_eq!(
+            parser.feed("echo a |\n"),
+            ParseStatus::NeedMore(Incomplete::Pipe)
+        );
+        assert_eq!(
+            parser.feed("cat \\\n"),
+            ParseStatus::NeedMore(Incomplete::Backslash)
+        );
+        assert_eq!(
+            parser.feed("-n\necho b\n"),
+            parse("echo a | cat -n; echo b")
+        );
+    }
+
+    #[test]
+    fn test_and_or_continue_on_the_next_line() {
+        let mut parser = Parser::new();
+        assert_eq!(
+            parser.feed("true &&\n"),
+            ParseStatus::NeedMore(Incomplete::AndOr)
+        );
+        assert_eq!(
+            parser.feed("false ||\n"),
+            ParseStatus::NeedMore(Incomplete::AndOr)
+        );
+        assert_eq!(parser.feed("echo a\n"), parse("true && false || echo a"));
+
+        assert_eq!(
+            parser.feed("echo a ||\n"),
+            ParseStatus::NeedMore(Incomplete::AndOr)
+        );
+        assert!(matches!(parser.finish(), ParseStatus::Error(..)));
+    }
+
+    #[test]
+    fn test_process_substitution_spans_lines() {
+        let mut parser = Parser::new();
+        assert_eq!(
+            parser.feed("cat <(echo a\n"),
+            ParseStatus::NeedMore(Incomplete::ProcessSubstitution)
+        );
+        assert_eq!(parser.feed("echo b)\n"), parse("cat <(echo a; echo b)"));
+    }
+
+    #[test]
+    fn test_errors_report_span_and_restart() {
+        let mut parser = Parser::new();
+        assert_eq!(
+            parser.feed("echo ok\necho ${x\n"),
+            ParseStatus::NeedMore(Incomplete::Parameter)
+        );
+        assert_eq!(
+            parser.finish(),
+            ParseStatus::Error(
+                Span { start: 8, end: 17 },
+                "Unclosed parameter expansion at end of input".to_string()
+            )
+        );
+        assert_eq!(parser.feed("echo ok\n"), parse("echo ok"));
+    }
+
+    #[test]
+    fn test_posix_errors_span_the_construct() {
+        let mut parser = Parser {
+            posix: true,
+            ..Parser::default()
+        };
+        assert_eq!(
+            parser.feed("echo a\n"),
+            ParseStatus::Complete(parse_tokens(Token::tokenize("echo a").unwrap()).unwrap())
+        );
+        assert_eq!(
+            parser.feed("echo '\nb' |\n"),
+            ParseStatus::NeedMore(Incomplete::Pipe)
+        );
+        assert_eq!(
+            parser.feed("cat <<< c\n"),
+            ParseStatus::Error(
+                Span { start: 16, end: 19 },
+                "<<< is not available in POSIX mode".to_string()
+            )
+        );
+        assert!(parser.is_idle());
+    }
+}
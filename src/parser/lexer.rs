@@ -1,15 +1,55 @@
-use crate::error::ShellError;
+use crate::{
+    error::ShellError,
+    parser::{
+        ast::{ProcessSubstitutionKind, Word, WordPart},
+        incremental::Incomplete,
+    },
+};
 use std::{iter::Peekable, str::Chars};
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Token {
     Word(String),
+    /// A word that contained quotes or backslash escapes, which is never a
+    /// reserved word or an alias.
+    QuotedWord(String),
+    /// A word containing parameter expansions, which have to be resolved
+    /// when the command runs.
+    ExpandableWord(Word),
     Pipe,
+    /// `&&` between pipelines.
+    AndIf,
+    /// `||` between pipelines.
+    OrIf,
     Semicolon,
     RedirectOut(i32),
     RedirectAppend(i32),
-    RedirectIn,
+    RedirectIn(i32),
     Background,
+    ProcessSubstitution(ProcessSubstitutionKind, String),
+    /// `name=value` in command position, before the command's name.
+    Assignment(String, Word),
+    /// `[[`, after which the lexer reads a conditional expression up to the
+    /// matching `]]`.
+    ConditionalStart,
+    ConditionalEnd,
+    And,
+    Or,
+    LeftParen,
+    RightParen,
+}
+
+/// How a word is delimited and whether its quoting is preserved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WordMode {
+    Command,
+    /// Inside `[[ ]]`: parentheses end words too, and quoted text is kept
+    /// apart from unquoted text so patterns can tell them apart.
+    Conditional,
+    /// The right-hand side of `=~`, where parentheses and `|` belong to the
+    /// regular expression and only whitespace ends the word.
+    Regex,
 }
 
 impl Token {
@@ -40,21 +80,44 @@ impl Token {
                     } else if next_c == '<' {
                         chars.next(); // consume digit
                         chars.next(); // consume '<'
-                        tokens.push(Token::RedirectIn);
+                        tokens.push(Token::RedirectIn(identifie));
                         continue;
                     }
                 }
             }
 
+            if c == '[' && Token::starts_conditional(&chars) && at_command_start(&tokens) {
+                chars.next(); // consume '['
+                chars.next(); // consume '['
+                tokens.push(Token::ConditionalStart);
+                Token::read_conditional(&mut chars, &mut tokens)?;
+                continue;
+            }
+
             match c {
                 '|' => {
                     chars.next();
-                    tokens.push(Token::Pipe);
+                    if chars.next_if_eq(&'|').is_some() {
+                        tokens.push(Token::OrIf);
+                    } else {
+                        tokens.push(Token::Pipe);
+                    }
                 }
                 ';' => {
                     chars.next();
                     tokens.push(Token::Semicolon);
                 }
+                '<' | '>' if chars.clone().nth(1) == Some('(') => {
+                    chars.next(); // consume '<' or '>'
+                    chars.next(); // consume '('
+                    let kind = if c == '<' {
+                        ProcessSubstitutionKind::Input
+                    } else {
+                        ProcessSubstitutionKind::Output
+                    };
+                    let source = Token::read_substitution(&mut chars)?;
+                    tokens.push(Token::ProcessSubstitution(kind, source));
+                }
                 '>' => {
                     chars.next();
                     if matches!(chars.peek(), Some(&'>')) {
@@ -66,16 +129,31 @@ impl Token {
                 }
                 '<' => {
                     chars.next();
-                    tokens.push(Token::RedirectIn);
+                    tokens.push(Token::RedirectIn(0));
                 }
                 '&' => {
                     chars.next();
-                    tokens.push(Token::Background);
+                    if chars.next_if_eq(&'&').is_some() {
+                        tokens.push(Token::AndIf);
+                    } else {
+                        tokens.push(Token::Background);
+                    }
                 }
                 _ => {
-                    let word = Token::read_word(&mut chars)?;
-                    if !word.is_empty() {
-                        tokens.push(Token::Word(word));
+                    let assigns = at_command_start(&tokens)
+                        || matches!(tokens.last(), Some(Token::Assignment(..)));
+                    if assigns && let Some(name) = Token::read_assignment_name(&mut chars) {
+                        let value = match Token::read_word(&mut chars, WordMode::Command)? {
+                            None => Word { parts: Vec::new() },
+                            Some(Token::Word(text) | Token::QuotedWord(text)) => {
+                                Word::literal(text)
+                            }
+                            Some(Token::ExpandableWord(word)) => word,
+                            Some(token) => unreachable!("read_word returned {:?}", token),
+                        };
+                        tokens.push(Token::Assignment(name, value));
+                    } else if let Some(word) = Token::read_word(&mut chars, WordMode::Command)? {
+                        tokens.push(word);
                     }
                 }
             }
@@ -84,12 +162,131 @@ impl Token {
         Ok(tokens)
     }
 
-    fn read_word(chars: &mut Peekable<Chars>) -> Result<String, ShellError> {
-        let mut word = String::new();
+    /// Whether `chars` starts with `[[` standing on its own as a word.
+    fn starts_conditional(chars: &Peekable<Chars>) -> bool {
+        let mut lookahead = chars.clone();
+        lookahead.next() == Some('[')
+            && lookahead.next() == Some('[')
+            && lookahead.next().is_none_or(char::is_whitespace)
+    }
+
+    /// Reads the tokens of a conditional expression after `[[`, up to and
+    /// including the closing `]]`.
+    fn read_conditional(
+        chars: &mut Peekable<Chars>,
+        tokens: &mut Vec<Token>,
+    ) -> Result<(), ShellError> {
+        let mut mode = WordMode::Conditional;
+
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+            let Some(&c) = chars.peek() else {
+                return Err(ShellError::Incomplete(Incomplete::Conditional));
+            };
+            let mut lookahead = chars.clone();
+            lookahead.next();
+            let next = lookahead.next();
+            let after = lookahead.next();
+
+            if mode == WordMode::Conditional {
+                let token = match (c, next) {
+                    (']', Some(']'))
+                        if after.is_none_or(|c| c.is_whitespace() || ";|&".contains(c)) =>
+                    {
+                        chars.next();
+                        chars.next();
+                        tokens.push(Token::ConditionalEnd);
+                        return Ok(());
+                    }
+                    ('&', Some('&')) => Some(Token::And),
+                    ('|', Some('|')) => Some(Token::Or),
+                    ('(', _) => Some(Token::LeftParen),
+                    (')', _) => Some(Token::RightParen),
+                    ('<' | '>', _) => Some(Token::ExpandableWord(Word {
+                        parts: vec![WordPart::Literal(c.to_string())],
+                    })),
+                    ('&' | '|' | ';', _) => {
+                        return Err(ShellError::ParseError(format!(
+                            "Unexpected token `{}' in conditional command",
+                            c
+                        )));
+                    }
+                    _ => None,
+                };
+                if let Some(token) = token {
+                    chars.next();
+                    if matches!(token, Token::And | Token::Or) {
+                        chars.next();
+                    }
+                    tokens.push(token);
+                    continue;
+                }
+            }
+
+            let word = Token::read_word(chars, mode)?;
+            mode = match &word {
+                Some(Token::ExpandableWord(Word { parts }))
+                    if parts == &[WordPart::Literal("=~".to_string())] =>
+                {
+                    WordMode::Regex
+                }
+                _ => WordMode::Conditional,
+            };
+            tokens.extend(word);
+        }
+    }
+
+    /// Reads the body of a `<(...)` or `>(...)` up to its matching `)`,
+    /// which is consumed but not returned.
+    fn read_substitution(chars: &mut Peekable<Chars>) -> Result<String, ShellError> {
+        let mut source = String::new();
+        let mut depth = 0;
+        let mut quote: Option<char> = None;
+
+        while let Some(c) = chars.next() {
+            match quote {
+                Some('\'') if c == '\'' => quote = None,
+                Some('"') if c == '"' => quote = None,
+                Some('"') if c == '\\' => {
+                    source.push(c);
+                    if let Some(escaped) = chars.next() {
+                        source.push(escaped);
+                    }
+                    continue;
+                }
+                Some(_) => {}
+                None => match c {
+                    '\'' | '"' => quote = Some(c),
+                    '\\' => {
+                        source.push(c);
+                        if let Some(escaped) = chars.next() {
+                            source.push(escaped);
+                        }
+                        continue;
+                    }
+                    '(' => depth += 1,
+                    ')' if depth == 0 => return Ok(source),
+                    ')' => depth -= 1,
+                    _ => {}
+                },
+            }
+            source.push(c);
+        }
+
+        Err(ShellError::Incomplete(Incomplete::ProcessSubstitution))
+    }
+
+    fn read_word(chars: &mut Peekable<Chars>, mode: WordMode) -> Result<Option<Token>, ShellError> {
+        let mut word = WordBuilder {
+            keep_quoting: mode != WordMode::Command,
+            ..WordBuilder::default()
+        };
 
         while let Some(&c) = chars.peek() {
             if c == '\'' {
                 chars.next();
+                word.quoted = true;
 
                 let mut found_closing = false;
                 while let Some(&ch) = chars.peek() {
@@ -98,12 +295,12 @@ impl Token {
                         found_closing = true;
                         break;
                     }
-                    word.push(ch);
+                    word.push_quoted(ch);
                     chars.next();
                 }
 
                 if !found_closing {
-                    return Err(ShellError::ParseError("Unclosed single quote".to_string()));
+                    return Err(ShellError::Incomplete(Incomplete::SingleQuote));
                 }
 
                 continue;
@@ -111,6 +308,7 @@ impl Token {
 
             if c == '"' {
                 chars.next();
+                word.quoted = true;
 
                 let mut found_closing = false;
                 while let Some(&ch) = chars.peek() {
@@ -119,32 +317,38 @@ impl Token {
                         found_closing = true;
                         break;
                     }
+                    if ch == '$'
+                        && let Some(name) = Token::read_parameter(chars)?
+                    {
+                        word.push_parameter(name, true);
+                        continue;
+                    }
                     if ch == '\\' {
                         chars.next();
                         if let Some(&escaped) = chars.peek() {
                             match escaped {
                                 '$' | '`' | '"' | '\\' => {
-                                    word.push(escaped);
+                                    word.push_quoted(escaped);
                                     chars.next();
                                 }
                                 '\n' => {
                                     chars.next();
                                 }
                                 _ => {
-                                    word.push('\\');
-                                    word.push(escaped);
+                                    word.push_quoted('\\');
+                                    word.push_quoted(escaped);
                                     chars.next();
                                 }
                             }
                         }
                     } else {
-                        word.push(ch);
+                        word.push_quoted(ch);
                         chars.next();
                     }
                 }
 
                 if !found_closing {
-                    return Err(ShellError::ParseError("Unclosed double quote".to_string()));
+                    return Err(ShellError::Incomplete(Incomplete::DoubleQuote));
                 }
 
                 continue;
@@ -153,15 +357,35 @@ impl Token {
             if c.is_whitespace() {
                 break;
             }
-            if matches!(c, '|' | ';' | '>' | '<' | '&') {
+            let ends_word = match mode {
+                WordMode::Command => matches!(c, '|' | ';' | '>' | '<' | '&'),
+                WordMode::Conditional => matches!(c, '|' | ';' | '>' | '<' | '&' | '(' | ')'),
+                WordMode::Regex => false,
+            };
+            if ends_word {
                 break;
             }
 
+            if c == '$'
+                && let Some(name) = Token::read_parameter(chars)?
+            {
+                word.push_parameter(name, false);
+                continue;
+            }
+
             if c == '\\' {
                 chars.next();
-                if let Some(&escaped) = chars.peek() {
-                    word.push(escaped);
-                    chars.next();
+                match chars.next() {
+                    // A backslash-newline joins the next line onto this one.
+                    Some('\n') if chars.peek().is_none() => {
+                        return Err(ShellError::Incomplete(Incomplete::Backslash));
+                    }
+                    Some('\n') => {}
+                    Some(escaped) => {
+                        word.quoted = true;
+                        word.push_quoted(escaped);
+                    }
+                    None => {}
                 }
             } else {
                 word.push(c);
@@ -169,20 +393,215 @@ impl Token {
             }
         }
 
-        Ok(word)
+        Ok(word.finish())
+    }
+
+    /// Reads the unquoted `name=` at the front of `chars`, if there is one,
+    /// and returns the name.
+    fn read_assignment_name(chars: &mut Peekable<Chars>) -> Option<String> {
+        let mut lookahead = chars.clone();
+        let mut name = String::new();
+        while let Some(ch) = lookahead.next_if(|&ch| ch.is_ascii_alphanumeric() || ch == '_') {
+            name.push(ch);
+        }
+        if name.is_empty()
+            || name.starts_with(|ch: char| ch.is_ascii_digit())
+            || lookahead.next() != Some('=')
+        {
+            return None;
+        }
+        *chars = lookahead;
+        Some(name)
+    }
+
+    /// Reads the parameter named by the `$` at the front of `chars`. Returns
+    /// `None`, consuming nothing, if the `$` does not start an expansion.
+    fn read_parameter(chars: &mut Peekable<Chars>) -> Result<Option<String>, ShellError> {
+        let mut lookahead = chars.clone();
+        lookahead.next(); // skip '$'
+
+        let name = match lookahead.peek() {
+            Some('{') => {
+                lookahead.next();
+                let mut name = String::new();
+                loop {
+                    match lookahead.next() {
+                        Some('}') => break,
+                        Some(ch) => name.push(ch),
+                        None => {
+                            return Err(ShellError::Incomplete(Incomplete::Parameter));
+                        }
+                    }
+                }
+                if !is_parameter_name(&name) {
+                    return Err(ShellError::ParseError(format!(
+                        "${{{}}}: bad substitution",
+                        name
+                    )));
+                }
+                name
+            }
+            Some(&ch)
+                if ch.is_ascii_digit() || matches!(ch, '?' | '!' | '#' | '$' | '@' | '*' | '-') =>
+            {
+                lookahead.next();
+                ch.to_string()
+            }
+            Some(&ch) if ch.is_ascii_alphabetic() || ch == '_' => {
+                let mut name = String::new();
+                while let Some(&ch) = lookahead.peek() {
+                    if !(ch.is_ascii_alphanumeric() || ch == '_') {
+                        break;
+                    }
+                    name.push(ch);
+                    lookahead.next();
+                }
+                name
+            }
+            _ => return Ok(None),
+        };
+
+        *chars = lookahead;
+        Ok(Some(name))
+    }
+}
+
+/// Whether `name` can appear inside `${...}`: a variable name, an array
+/// element such as `name[1]` or `name[@]`, a positional parameter or a
+/// special parameter.
+fn is_parameter_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            let (name, subscript) = match chars.as_str().split_once('[') {
+                Some((name, rest)) => (name, rest.strip_suffix(']')),
+                None => (chars.as_str(), Some("0")),
+            };
+            name.chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+                && subscript.is_some_and(|subscript| {
+                    subscript == "@"
+                        || subscript == "*"
+                        || (!subscript.is_empty()
+                            && subscript.chars().all(|ch| ch.is_ascii_digit()))
+                })
+        }
+        Some(first) if first.is_ascii_digit() => chars.all(|ch| ch.is_ascii_digit()),
+        Some('?' | '!' | '#' | '$' | '@' | '*' | '-') => chars.next().is_none(),
+        _ => false,
+    }
+}
+
+/// Whether a `[[` at the end of `tokens` would be in command position.
+fn at_command_start(tokens: &[Token]) -> bool {
+    match tokens.split_last() {
+        None => true,
+        Some((
+            Token::Pipe | Token::AndIf | Token::OrIf | Token::Semicolon | Token::Background,
+            _,
+        )) => true,
+        Some((Token::Word(word), rest)) if word == "!" || word == "time" => at_command_start(rest),
+        Some((Token::Word(word), rest)) if word == "-p" => {
+            matches!(rest.last(), Some(Token::Word(time)) if time == "time")
+                && at_command_start(&rest[..rest.len() - 1])
+        }
+        _ => false,
+    }
+}
+
+/// Collects the parts of a word as the lexer reads it.
+#[derive(Default)]
+struct WordBuilder {
+    parts: Vec<WordPart>,
+    literal: String,
+    /// Whether `literal` holds quoted text; only tracked with `keep_quoting`.
+    literal_quoted: bool,
+    quoted: bool,
+    keep_quoting: bool,
+}
+
+impl WordBuilder {
+    fn push(&mut self, c: char) {
+        self.push_text(c, false);
+    }
+
+    fn push_quoted(&mut self, c: char) {
+        self.push_text(c, self.keep_quoting);
+    }
+
+    fn push_text(&mut self, c: char, quoted: bool) {
+        if quoted != self.literal_quoted {
+            self.flush_literal();
+            self.literal_quoted = quoted;
+        }
+        self.literal.push(c);
+    }
+
+    fn push_parameter(&mut self, name: String, quoted: bool) {
+        self.flush_literal();
+        self.parts.push(WordPart::Parameter { name, quoted });
+    }
+
+    fn flush_literal(&mut self) {
+        if !self.literal.is_empty() {
+            let text = std::mem::take(&mut self.literal);
+            self.parts.push(if self.literal_quoted {
+                WordPart::Quoted(text)
+            } else {
+                WordPart::Literal(text)
+            });
+        }
+    }
+
+    /// Plain words become `Token::Word`, or `Token::QuotedWord` if they
+    /// contained quoting. A word made only of quotes, such as `''`, is still
+    /// a word, just an empty one. Words that keep their quoting are always
+    /// `Token::ExpandableWord`.
+    fn finish(mut self) -> Option<Token> {
+        self.flush_literal();
+        if self.keep_quoting {
+            if self.parts.is_empty() && self.quoted {
+                self.parts.push(WordPart::Quoted(String::new()));
+            }
+            return (!self.parts.is_empty())
+                .then_some(Token::ExpandableWord(Word { parts: self.parts }));
+        }
+        if self.parts.is_empty() {
+            return self.quoted.then(|| Token::QuotedWord(String::new()));
+        }
+        if self
+            .parts
+            .iter()
+            .all(|part| matches!(part, WordPart::Literal(_)))
+        {
+            let text = Word { parts: self.parts }.literal_text();
+            return Some(if self.quoted {
+                Token::QuotedWord(text)
+            } else {
+                Token::Word(text)
+            });
+        }
+        Some(Token::ExpandableWord(Word { parts: self.parts }))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Token;
+    use crate::parser::{
+        ast::{AndOr, ProcessSubstitutionKind, Word, WordPart},
+        parse_tokens,
+    };
     #[test]
     fn test_backslash_escapes_spaces() {
         let input = r"echo world\ \ \ \ \ \ script";
         let tokens = Token::tokenize(input).unwrap();
         assert_eq!(tokens.len(), 2);
         assert_eq!(tokens[0], Token::Word("echo".to_string()));
-        assert_eq!(tokens[1], Token::Word("world      script".to_string()));
+        assert_eq!(
+            tokens[1],
+            Token::QuotedWord("world      script".to_string())
+        );
     }
 
     #[test]
@@ -191,7 +610,7 @@ mod tests {
         let tokens = Token::tokenize(input).unwrap();
         assert_eq!(tokens.len(), 2);
         assert_eq!(tokens[0], Token::Word("echo".to_string()));
-        assert_eq!(tokens[1], Token::Word("before after".to_string()));
+        assert_eq!(tokens[1], Token::QuotedWord("before after".to_string()));
     }
 
     #[test]
@@ -200,7 +619,7 @@ mod tests {
         let tokens = Token::tokenize(input).unwrap();
         assert_eq!(tokens.len(), 2);
         assert_eq!(tokens[0], Token::Word("echo".to_string()));
-        assert_eq!(tokens[1], Token::Word("testnexample".to_string()));
+        assert_eq!(tokens[1], Token::QuotedWord("testnexample".to_string()));
     }
 
     #[test]
@@ -209,7 +628,7 @@ mod tests {
         let tokens = Token::tokenize(input).unwrap();
         assert_eq!(tokens.len(), 2);
         assert_eq!(tokens[0], Token::Word("echo".to_string()));
-        assert_eq!(tokens[1], Token::Word("hello\\world".to_string()));
+        assert_eq!(tokens[1], Token::QuotedWord("hello\\world".to_string()));
     }
 
     #[test]
@@ -218,6 +637,205 @@ mod tests {
         let tokens = Token::tokenize(input).unwrap();
         assert_eq!(tokens.len(), 2);
         assert_eq!(tokens[0], Token::Word("echo".to_string()));
-        assert_eq!(tokens[1], Token::Word("'hello'".to_string()));
+        assert_eq!(tokens[1], Token::QuotedWord("'hello'".to_string()));
+    }
+
+    #[test]
+    fn test_parameter_expansion_parts() {
+        let input = r#"echo "$HOME/x" ${1}$@ '$q' \$v "" $"#;
+        let tokens = Token::tokenize(input).unwrap();
+        assert_eq!(tokens.len(), 7);
+        assert_eq!(
+            tokens[1],
+            Token::ExpandableWord(Word {
+                parts: vec![
+                    WordPart::Parameter {
+                        name: "HOME".to_string(),
+                        quoted: true
+                    },
+                    WordPart::Literal("/x".to_string()),
+                ]
+            })
+        );
+        assert_eq!(
+            tokens[2],
+            Token::ExpandableWord(Word {
+                parts: vec![
+                    WordPart::Parameter {
+                        name: "1".to_string(),
+                        quoted: false
+                    },
+                    WordPart::Parameter {
+                        name: "@".to_string(),
+                        quoted: false
+                    },
+                ]
+            })
+        );
+        assert_eq!(tokens[3], Token::QuotedWord("$q".to_string()));
+        assert_eq!(tokens[4], Token::QuotedWord("$v".to_string()));
+        assert_eq!(tokens[5], Token::QuotedWord(String::new()));
+        assert_eq!(tokens[6], Token::Word("$".to_string()));
+    }
+
+    #[test]
+    fn test_quoted_words_are_not_reserved() {
+        let input = r"! '!' \time time";
+        let tokens = Token::tokenize(input).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("!".to_string()),
+                Token::QuotedWord("!".to_string()),
+                Token::QuotedWord("time".to_string()),
+                Token::Word("time".to_string()),
+            ]
+        );
+
+        let pipelines = parse_tokens(Token::tokenize("'!' true; \\time x").unwrap()).unwrap();
+        assert!(!pipelines[0].negated);
+        assert_eq!(pipelines[0].commands[0].words[0].literal_text(), "!");
+        assert_eq!(pipelines[1].time, None);
+        assert_eq!(pipelines[1].commands[0].words[0].literal_text(), "time");
+    }
+
+    #[test]
+    fn test_any_quoting_makes_a_quoted_word() {
+        let input = r#"plain ti'm'e "a"b a\b '' "" x''"#;
+        let tokens = Token::tokenize(input).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("plain".to_string()),
+                Token::QuotedWord("time".to_string()),
+                Token::QuotedWord("ab".to_string()),
+                Token::QuotedWord("ab".to_string()),
+                Token::QuotedWord(String::new()),
+                Token::QuotedWord(String::new()),
+                Token::QuotedWord("x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quoted_brackets_do_not_start_a_conditional() {
+        for input in ["'[[' -n x ]]", r"\[[ -n x ]]"] {
+            assert_eq!(
+                Token::tokenize(input).unwrap(),
+                vec![
+                    Token::QuotedWord("[[".to_string()),
+                    Token::Word("-n".to_string()),
+                    Token::Word("x".to_string()),
+                    Token::Word("]]".to_string()),
+                ],
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_assignments_before_the_command_name() {
+        let input = r"x=1 y='a b' z= echo w=2; 'v=3' 1=x";
+        let tokens = Token::tokenize(input).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Assignment("x".to_string(), Word::literal("1".to_string())),
+                Token::Assignment("y".to_string(), Word::literal("a b".to_string())),
+                Token::Assignment("z".to_string(), Word { parts: Vec::new() }),
+                Token::Word("echo".to_string()),
+                Token::Word("w=2".to_string()),
+                Token::Semicolon,
+                Token::QuotedWord("v=3".to_string()),
+                Token::Word("1=x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conditional_mode() {
+        let literal = |text: &str| {
+            Token::ExpandableWord(Word {
+                parts: vec![WordPart::Literal(text.to_string())],
+            })
+        };
+        let input = r#"[[ ! -f "a b"* && ($x =~ ^(a|b)$ || a<b) ]]; echo [["#;
+        let tokens = Token::tokenize(input).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::ConditionalStart,
+                literal("!"),
+                literal("-f"),
+                Token::ExpandableWord(Word {
+                    parts: vec![
+                        WordPart::Quoted("a b".to_string()),
+                        WordPart::Literal("*".to_string()),
+                    ]
+                }),
+                Token::And,
+                Token::LeftParen,
+                Token::ExpandableWord(Word {
+                    parts: vec![WordPart::Parameter {
+                        name: "x".to_string(),
+                        quoted: false
+                    }]
+                }),
+                literal("=~"),
+                literal("^(a|b)$"),
+                Token::Or,
+                literal("a"),
+                literal("<"),
+                literal("b"),
+                Token::RightParen,
+                Token::ConditionalEnd,
+                Token::Semicolon,
+                Token::Word("echo".to_string()),
+                Token::Word("[[".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_substitution() {
+        let input = r"diff <(sort 'a)') >(tee (b))";
+        let tokens = Token::tokenize(input).unwrap();
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(
+            tokens[1],
+            Token::ProcessSubstitution(ProcessSubstitutionKind::Input, "sort 'a)'".to_string())
+        );
+        assert_eq!(
+            tokens[2],
+            Token::ProcessSubstitution(ProcessSubstitutionKind::Output, "tee (b)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_and_or_operators() {
+        let word = |text: &str| Token::Word(text.to_string());
+        let tokens = Token::tokenize("a&&x=1 b||c|d&e").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                word("a"),
+                Token::AndIf,
+                Token::Assignment("x".to_string(), Word::literal("1".to_string())),
+                word("b"),
+                Token::OrIf,
+                word("c"),
+                Token::Pipe,
+                word("d"),
+                Token::Background,
+                word("e"),
+            ]
+        );
+
+        let parsed = parse_tokens(tokens).unwrap();
+        let and_or: Vec<_> = parsed.iter().map(|pipeline| pipeline.and_or).collect();
+        assert_eq!(and_or, vec![None, Some(AndOr::And), Some(AndOr::Or), None]);
+        assert!(parsed[2].background);
+        assert!(parse_tokens(Token::tokenize("a &&").unwrap()).is_err());
     }
 }
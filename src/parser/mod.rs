@@ -1,30 +1,104 @@
 use crate::{
     error::ShellError,
     parser::{
-        ast::{Command, Pipeline, Redirection},
+        ast::{
+            AndOr, Assignment, Command, Pipeline, ProcessSubstitution, Redirection,
+            RedirectionKind, TimeFormat, Word, WordPart,
+        },
+        conditional::parse_conditional,
+        incremental::{ParseStatus, Parser},
         lexer::Token,
     },
 };
 
+pub mod alias;
 pub mod ast;
+pub mod conditional;
+pub mod incremental;
 pub mod lexer;
+pub mod posix;
+pub mod printer;
 
 pub fn parse_tokens(tokens: Vec<Token>) -> Result<Vec<Pipeline>, ShellError> {
     let mut tokens_iter = tokens.into_iter().peekable();
 
     let mut pipelines: Vec<Pipeline> = Vec::new();
+    let mut and_or = None;
 
     while tokens_iter.peek().is_some() {
-        let pipeline = parse_pipeline(&mut tokens_iter)?;
+        let mut pipeline = parse_pipeline(&mut tokens_iter)?;
+        pipeline.and_or = and_or.take();
+
+        match tokens_iter.peek() {
+            Some(Token::Semicolon) => {
+                tokens_iter.next();
+            }
+            Some(Token::Background) => {
+                tokens_iter.next();
+                pipeline.background = true;
+            }
+            Some(Token::AndIf) => {
+                tokens_iter.next();
+                and_or = Some(AndOr::And);
+            }
+            Some(Token::OrIf) => {
+                tokens_iter.next();
+                and_or = Some(AndOr::Or);
+            }
+            _ => {}
+        }
         pipelines.push(pipeline);
     }
 
-    Ok(pipelines)
+    match and_or {
+        Some(AndOr::And) => Err(ShellError::ParseError(
+            "Expected a command after '&&'".to_string(),
+        )),
+        Some(AndOr::Or) => Err(ShellError::ParseError(
+            "Expected a command after '||'".to_string(),
+        )),
+        None => Ok(pipelines),
+    }
 }
 fn parse_pipeline(
     tokens_iter: &mut std::iter::Peekable<std::vec::IntoIter<Token>>,
 ) -> Result<Pipeline, ShellError> {
+    let mut negated = false;
+    let mut time = None;
+
+    loop {
+        match tokens_iter.peek() {
+            Some(Token::Word(word)) if word == "!" => {
+                tokens_iter.next();
+                negated = !negated;
+            }
+            Some(Token::Word(word)) if word == "time" && time.is_none() => {
+                tokens_iter.next();
+                time = Some(TimeFormat::Variable);
+                if matches!(tokens_iter.peek(), Some(Token::Word(word)) if word == "-p") {
+                    tokens_iter.next();
+                    time = Some(TimeFormat::Posix);
+                }
+            }
+            _ => break,
+        }
+    }
+
     let mut commands: Vec<Command> = Vec::new();
+    if time.is_some()
+        && matches!(
+            tokens_iter.peek(),
+            None | Some(Token::Semicolon | Token::Background | Token::AndIf | Token::OrIf)
+        )
+    {
+        return Ok(Pipeline {
+            and_or: None,
+            commands,
+            negated,
+            time,
+            background: false,
+        });
+    }
     commands.push(parse_command(tokens_iter)?);
 
     while let Some(token) = tokens_iter.peek() {
@@ -36,79 +110,173 @@ fn parse_pipeline(
         }
     }
 
-    Ok(Pipeline { commands })
+    Ok(Pipeline {
+        and_or: None,
+        commands,
+        negated,
+        time,
+        background: false,
+    })
 }
 
 fn parse_command(
     tokens_iter: &mut std::iter::Peekable<std::vec::IntoIter<Token>>,
 ) -> Result<Command, ShellError> {
-    let program = match tokens_iter.next() {
-        Some(Token::Word(s)) => s,
-        _ => {
-            return Err(ShellError::ParseError(
-                "Unexpected end of input".to_string(),
-            ));
-        }
-    };
+    let mut assignments: Vec<Assignment> = Vec::new();
+    let mut words: Vec<Word> = Vec::new();
+    let mut conditional = None;
+    if tokens_iter.next_if_eq(&Token::ConditionalStart).is_some() {
+        conditional = Some(parse_conditional(tokens_iter)?);
+    }
 
-    let mut arguments: Vec<String> = Vec::new();
-    let mut input_file: Option<String> = None;
-    let mut output_redirections: Vec<Redirection> = Vec::new();
+    let mut redirections: Vec<Redirection> = Vec::new();
 
     while let Some(token) = tokens_iter.peek() {
         match token {
-            Token::Pipe | Token::Semicolon | Token::Background => break,
-            Token::RedirectIn => {
+            Token::Pipe | Token::AndIf | Token::OrIf | Token::Semicolon | Token::Background => {
+                break;
+            }
+            Token::RedirectIn(fd) => {
+                let fd = *fd;
                 tokens_iter.next();
-                if let Some(Token::Word(file)) = tokens_iter.next() {
-                    input_file = Some(file);
-                } else {
-                    return Err(ShellError::ParseError(
-                        "Expected file name after '<'".to_string(),
-                    ));
-                }
+                redirections.push(parse_redirection(
+                    tokens_iter,
+                    fd,
+                    RedirectionKind::Input,
+                    "<",
+                )?);
             }
             Token::RedirectOut(fd) => {
                 let fd = *fd;
                 tokens_iter.next();
-                if let Some(Token::Word(file)) = tokens_iter.next() {
-                    output_redirections.push(Redirection {
-                        path: file,
-                        fd,
-                        append: false,
-                    });
-                } else {
-                    return Err(ShellError::ParseError(
-                        "Expected file name after '>'".to_string(),
-                    ));
-                }
+                redirections.push(parse_redirection(
+                    tokens_iter,
+                    fd,
+                    RedirectionKind::Output,
+                    ">",
+                )?);
             }
             Token::RedirectAppend(fd) => {
                 let fd = *fd;
                 tokens_iter.next();
-                if let Some(Token::Word(file)) = tokens_iter.next() {
-                    output_redirections.push(Redirection {
-                        path: file,
-                        fd,
-                        append: true,
-                    });
-                } else {
-                    return Err(ShellError::ParseError(
-                        "Expected file name after '>>'".to_string(),
-                    ));
+                redirections.push(parse_redirection(
+                    tokens_iter,
+                    fd,
+                    RedirectionKind::Append,
+                    ">>",
+                )?);
+            }
+            Token::Assignment(..) if words.is_empty() && conditional.is_none() => {
+                if let Some(Token::Assignment(name, value)) = tokens_iter.next() {
+                    assignments.push(Assignment { name, value });
                 }
             }
-            Token::Word(arg) => {
-                arguments.push(arg.clone());
-                tokens_iter.next();
+            Token::Word(_)
+            | Token::QuotedWord(_)
+            | Token::ExpandableWord(_)
+            | Token::ProcessSubstitution(..)
+            | Token::Assignment(..)
+                if conditional.is_none() =>
+            {
+                let token = tokens_iter.next().unwrap();
+                words.push(parse_word(token)?);
+            }
+            _ => {
+                return Err(ShellError::ParseError(format!(
+                    "Unexpected token {:?}",
+                    token
+                )));
             }
         }
     }
 
+    if assignments.is_empty()
+        && words.is_empty()
+        && redirections.is_empty()
+        && conditional.is_none()
+    {
+        return Err(ShellError::ParseError(
+            "Unexpected end of input".to_string(),
+        ));
+    }
+
     Ok(Command {
-        program,
-        arguments,
-        input: input_file,
-        outputs: output_redirections,
+        assignments,
+        words,
+        redirections,
+        conditional,
     })
 }
+
+fn parse_redirection(
+    tokens_iter: &mut std::iter::Peekable<std::vec::IntoIter<Token>>,
+    fd: i32,
+    kind: RedirectionKind,
+    operator: &str,
+) -> Result<Redirection, ShellError> {
+    match tokens_iter.next() {
+        Some(
+            token @ (Token::Word(_)
+            | Token::QuotedWord(_)
+            | Token::ExpandableWord(_)
+            | Token::ProcessSubstitution(..)),
+        ) => {
+            let target = parse_word(token)?;
+            Ok(Redirection {
+                path: target.literal_text(),
+                target,
+                fd,
+                kind,
+            })
+        }
+        _ => Err(ShellError::ParseError(format!(
+            "Expected file name after '{}'",
+            operator
+        ))),
+    }
+}
+
+fn parse_word(token: Token) -> Result<Word, ShellError> {
+    match token {
+        Token::Word(text) | Token::QuotedWord(text) => Ok(Word::literal(text)),
+        Token::ExpandableWord(word) => Ok(word),
+        // After the command name, `name=value` is an ordinary word.
+        Token::Assignment(name, value) => {
+            let mut parts = vec![WordPart::Literal(format!("{}=", name))];
+            for part in value.parts {
+                match (parts.last_mut(), part) {
+                    (Some(WordPart::Literal(text)), WordPart::Literal(more)) => {
+                        text.push_str(&more)
+                    }
+                    (_, part) => parts.push(part),
+                }
+            }
+            Ok(Word { parts })
+        }
+        Token::ProcessSubstitution(kind, source) => {
+            let pipelines = parse_list(&source)?;
+            Ok(Word {
+                parts: vec![WordPart::ProcessSubstitution(ProcessSubstitution {
+                    kind,
+                    pipelines,
+                })],
+            })
+        }
+        _ => Err(ShellError::ParseError("Expected a word".to_string())),
+    }
+}
+
+/// Parses the body of a process substitution, where newlines separate
+/// commands as they do at the top level.
+fn parse_list(source: &str) -> Result<Vec<Pipeline>, ShellError> {
+    let mut parser = Parser::new();
+    let mut pipelines = Vec::new();
+    for status in [parser.feed(source), parser.finish()] {
+        match status {
+            ParseStatus::Complete(complete) => pipelines.extend(complete),
+            ParseStatus::NeedMore(_) => {}
+            ParseStatus::Error(_, message) => return Err(ShellError::ParseError(message)),
+        }
+    }
+    Ok(pipelines)
+}
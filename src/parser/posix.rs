@@ -0,0 +1,168 @@
+use crate::{
+    error::ShellError,
+    parser::{
+        ast::{Pipeline, Word, WordPart},
+        incremental::Span,
+    },
+};
+
+/// Rejects the bash extensions that a POSIX shell such as dash would not
+/// accept: `[[ ]]`, process substitution and array subscripts.
+pub fn check_posix(pipelines: &[Pipeline]) -> Result<(), ShellError> {
+    for command in pipelines.iter().flat_map(|pipeline| &pipeline.commands) {
+        if command.conditional.is_some() {
+            return Err(not_posix("[[ ]]"));
+        }
+        let targets = command
+            .redirections
+            .iter()
+            .map(|redirection| &redirection.target);
+        let values = command
+            .assignments
+            .iter()
+            .map(|assignment| &assignment.value);
+        for word in values.chain(&command.words).chain(targets) {
+            check_word(word)?;
+        }
+    }
+    Ok(())
+}
+
+fn check_word(word: &Word) -> Result<(), ShellError> {
+    for part in &word.parts {
+        match part {
+            WordPart::ProcessSubstitution(_) => return Err(not_posix("Process substitution")),
+            WordPart::Parameter { name, .. } if name.contains('[') => {
+                return Err(not_posix(&format!("${{{}}}: array", name)));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Rejects the bash extensions that the lexer reads as something else or not
+/// at all, so they never reach the AST: `$'...'` quoting, `<<<`, `&>` and
+/// the `function` keyword. The span covers the first one in `source`.
+pub fn check_posix_source(source: &str) -> Result<(), (Span, ShellError)> {
+    let found = |start: usize, end: usize, construct: &str| {
+        Err((Span { start, end }, not_posix(construct)))
+    };
+    // Whether the next word starts a command, where `function` is a keyword.
+    let mut command_start = true;
+    let mut chars = source.char_indices();
+    while let Some((index, c)) = chars.next() {
+        let rest = &source[index..];
+        match c {
+            '$' if rest.starts_with("$'") => {
+                let end = rest[2..]
+                    .find('\'')
+                    .map_or(source.len(), |close| index + close + 3);
+                return found(index, end, "$'...' quoting");
+            }
+            '<' if rest.starts_with("<<<") => return found(index, index + 3, "<<<"),
+            '&' if rest.starts_with("&>") => {
+                let end = index + if rest.starts_with("&>>") { 3 } else { 2 };
+                return found(index, end, &source[index..end]);
+            }
+            'f' if command_start
+                && rest.strip_prefix("function").is_some_and(|after| {
+                    after.is_empty() || after.starts_with(char::is_whitespace)
+                }) =>
+            {
+                return found(index, index + "function".len(), "function");
+            }
+            '\\' => {
+                chars.next();
+            }
+            '\'' => while chars.next().is_some_and(|(_, c)| c != '\'') {},
+            '"' => {
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => {
+                            chars.next();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        command_start = match c {
+            ';' | '&' | '|' | '(' | '\n' => true,
+            c if c.is_whitespace() => command_start,
+            _ => false,
+        };
+    }
+    Ok(())
+}
+
+fn not_posix(construct: &str) -> ShellError {
+    ShellError::ParseError(format!("{} is not available in POSIX mode", construct))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_posix, check_posix_source};
+    use crate::parser::{incremental::Span, lexer::Token, parse_tokens};
+
+    fn check(input: &str) -> Result<(), String> {
+        let pipelines = parse_tokens(Token::tokenize(input).unwrap()).unwrap();
+        check_posix(&pipelines).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_rejects_bash_extensions() {
+        assert!(check("echo \"$1\" | tr a b > out; time -p ! true").is_ok());
+        assert!(check("[[ -f x ]]").is_err());
+        assert!(check("cat <(ls)").is_err());
+        assert!(check("echo > ${a[0]}").is_err());
+    }
+
+    /// The span and message of the first extension in `source`.
+    fn find(source: &str) -> Option<(Span, String)> {
+        check_posix_source(source)
+            .err()
+            .map(|(span, e)| (span, e.to_string()))
+    }
+
+    fn span(start: usize, end: usize) -> Option<Span> {
+        Some(Span { start, end })
+    }
+
+    #[test]
+    fn test_rejects_ansi_c_quoting() {
+        let (found, message) = find("echo $'a\\tb' c").unwrap();
+        assert_eq!(Some(found), span(5, 12));
+        assert!(message.contains("$'...'"));
+        assert_eq!(find("echo $'a").map(|(span, _)| span), span(5, 8));
+        assert!(find("echo '$' \"$'\" \\$'a'").is_none());
+    }
+
+    #[test]
+    fn test_rejects_here_strings() {
+        let (found, message) = find("cat <<< word").unwrap();
+        assert_eq!(Some(found), span(4, 7));
+        assert!(message.contains("<<<"));
+        assert!(find("cat '<<<' \"<<<\" < in").is_none());
+    }
+
+    #[test]
+    fn test_rejects_function_keyword() {
+        let (found, message) = find("true; function f { :; }").unwrap();
+        assert_eq!(Some(found), span(6, 14));
+        assert!(message.contains("function"));
+        assert_eq!(find("function\n").map(|(span, _)| span), span(0, 8));
+        assert!(find("echo function; functions; f() { :; }").is_none());
+    }
+
+    #[test]
+    fn test_rejects_output_to_both_streams() {
+        let (found, message) = find("make &> log").unwrap();
+        assert_eq!(Some(found), span(5, 7));
+        assert!(message.contains("&>"));
+        assert_eq!(find("make &>> log").map(|(span, _)| span), span(5, 8));
+        assert!(find("make > log 2>&1 & echo '&>'").is_none());
+    }
+}
@@ -0,0 +1,513 @@
+//! Renders `parser::ast` back to shell source.
+//!
+//! The output is canonical: every literal is quoted only when it has to be,
+//! parameters are always braced, and pipelines are separated by `; ` unless
+//! `&&` or `||` joins them. Feeding
+//! the output of `print_pipelines` back through `Token::tokenize` and
+//! `parse_tokens` gives back the same AST. A command named like a reserved
+//! word, such as `time`, or like an assignment is quoted so that it is not
+//! read as one.
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::parser::ast::{
+    AndOr, Command, ConditionalExpression, Pipeline, ProcessSubstitution, ProcessSubstitutionKind,
+    Redirection, RedirectionKind, TimeFormat, Word, WordPart,
+};
+
+/// Quotes `value` so that the shell reads it back as the same single word.
+pub fn single_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Quotes `value` only if it contains characters the lexer would treat
+/// specially.
+pub fn quote(value: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_./,:+=@%^".contains(c);
+    if !value.is_empty() && value.chars().all(is_plain) {
+        value.to_string()
+    } else {
+        single_quote(value)
+    }
+}
+
+pub fn print_pipelines(pipelines: &[Pipeline]) -> String {
+    let mut printed = String::new();
+    for (i, pipeline) in pipelines.iter().enumerate() {
+        if i > 0 {
+            printed.push_str(match pipeline.and_or {
+                Some(AndOr::And) => " && ",
+                Some(AndOr::Or) => " || ",
+                // `&` already ends the pipeline before it.
+                None if pipelines[i - 1].background => " ",
+                None => "; ",
+            });
+        }
+        printed.push_str(&pipeline.to_string());
+    }
+    printed
+}
+
+impl Display for Word {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.parts.is_empty() {
+            return f.write_str("''");
+        }
+        for part in &self.parts {
+            match part {
+                WordPart::Literal(text) => f.write_str(&quote(text))?,
+                WordPart::Quoted(text) => f.write_str(&single_quote(text))?,
+                WordPart::Parameter { name, quoted: true } => write!(f, "\"${{{}}}\"", name)?,
+                WordPart::Parameter {
+                    name,
+                    quoted: false,
+                } => write!(f, "${{{}}}", name)?,
+                WordPart::ProcessSubstitution(substitution) => write!(f, "{}", substitution)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for ProcessSubstitution {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let operator = match self.kind {
+            ProcessSubstitutionKind::Input => '<',
+            ProcessSubstitutionKind::Output => '>',
+        };
+        write!(f, "{}({})", operator, print_pipelines(&self.pipelines))
+    }
+}
+
+/// Renders a word inside `[[ ]]`. Unquoted text there may be a pattern, so
+/// it is written as is rather than quoted.
+fn conditional_word(word: &Word) -> String {
+    word.parts
+        .iter()
+        .map(|part| match part {
+            WordPart::Literal(text) => text.clone(),
+            _ => Word {
+                parts: vec![part.clone()],
+            }
+            .to_string(),
+        })
+        .collect()
+}
+
+impl Display for ConditionalExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let grouped = |expression: &ConditionalExpression, group: bool| {
+            if group {
+                format!("( {} )", expression)
+            } else {
+                expression.to_string()
+            }
+        };
+        let is_or = |expression: &ConditionalExpression| {
+            matches!(expression, ConditionalExpression::Or(..))
+        };
+        let is_and = |expression: &ConditionalExpression| {
+            matches!(expression, ConditionalExpression::And(..))
+        };
+
+        match self {
+            ConditionalExpression::Word(word) => f.write_str(&conditional_word(word)),
+            ConditionalExpression::Unary { operator, operand } => {
+                write!(f, "{} {}", operator, conditional_word(operand))
+            }
+            ConditionalExpression::Binary {
+                operator,
+                left,
+                right,
+            } => write!(
+                f,
+                "{} {} {}",
+                conditional_word(left),
+                operator,
+                conditional_word(right)
+            ),
+            ConditionalExpression::Not(inner) => {
+                write!(f, "! {}", grouped(inner, is_or(inner) || is_and(inner)))
+            }
+            ConditionalExpression::And(left, right) => write!(
+                f,
+                "{} && {}",
+                grouped(left, is_or(left)),
+                grouped(right, is_or(right) || is_and(right))
+            ),
+            ConditionalExpression::Or(left, right) => {
+                write!(f, "{} || {}", left, grouped(right, is_or(right)))
+            }
+        }
+    }
+}
+
+impl Display for Redirection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.fd != self.kind.default_fd() {
+            write!(f, "{}", self.fd)?;
+        }
+        let operator = match self.kind {
+            RedirectionKind::Input => "<",
+            RedirectionKind::Output => ">",
+            RedirectionKind::Append => ">>",
+        };
+        write!(f, "{} {}", operator, self.target)
+    }
+}
+
+impl Command {
+    /// Renders the command, quoting its first word if it would otherwise
+    /// read as one of `reserved` or as an assignment.
+    fn print(&self, reserved: &[&str]) -> String {
+        let mut printed = String::new();
+        if let Some(conditional) = &self.conditional {
+            printed.push_str(&format!("[[ {} ]]", conditional));
+        }
+        let assignments = self.assignments.iter().map(|assignment| {
+            if assignment.value.parts.is_empty() {
+                format!("{}=", assignment.name)
+            } else {
+                format!("{}={}", assignment.name, assignment.value)
+            }
+        });
+        let words = self
+            .words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| match word.parts.as_slice() {
+                [WordPart::Literal(text)] if i == 0 && reserved.contains(&text.as_str()) => {
+                    single_quote(text)
+                }
+                // A command name such as `a=b` would read as an assignment.
+                [WordPart::Literal(text), rest @ ..] if i == 0 && starts_assignment(text) => {
+                    let rest = Word {
+                        parts: rest.to_vec(),
+                    };
+                    if rest.parts.is_empty() {
+                        single_quote(text)
+                    } else {
+                        format!("{}{}", single_quote(text), rest)
+                    }
+                }
+                _ => word.to_string(),
+            });
+        let words: Vec<String> = assignments.chain(words).collect();
+        printed.push_str(&words.join(" "));
+        for redirection in &self.redirections {
+            printed.push_str(&format!(" {}", redirection));
+        }
+        printed
+    }
+}
+
+/// Whether `text` starts with `name=`, as an assignment does.
+fn starts_assignment(text: &str) -> bool {
+    text.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.print(&["time"]))
+    }
+}
+
+impl Display for Pipeline {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut prefixes = Vec::new();
+        match self.time {
+            Some(TimeFormat::Variable) => prefixes.push("time"),
+            Some(TimeFormat::Posix) => prefixes.push("time -p"),
+            None => {}
+        }
+        if self.negated {
+            prefixes.push("!");
+        }
+
+        // After a bare `time`, a command named `-p` would read as its option.
+        let first_reserved: &[&str] = if self.time == Some(TimeFormat::Variable) && !self.negated {
+            &["time", "-p"]
+        } else {
+            &["time"]
+        };
+        let commands: Vec<String> = self
+            .commands
+            .iter()
+            .enumerate()
+            .map(|(i, command)| {
+                let reserved = if i == 0 { first_reserved } else { &["time"] };
+                command.print(reserved)
+            })
+            .collect();
+        if !commands.is_empty() {
+            prefixes.push("");
+        }
+        write!(f, "{}{}", prefixes.join(" "), commands.join(" | "))?;
+        if self.background {
+            f.write_str(" &")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::print_pipelines;
+    use crate::parser::{
+        ast::{
+            AndOr, Assignment, Command, ConditionalExpression, Pipeline, ProcessSubstitution,
+            ProcessSubstitutionKind, Redirection, RedirectionKind, TimeFormat, Word, WordPart,
+        },
+        conditional::{BINARY_OPERATORS, UNARY_OPERATORS},
+        lexer::Token,
+        parse_tokens,
+    };
+
+    /// A small xorshift generator, so the property test is reproducible
+    /// without pulling in a dependency.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> usize {
+            (self.next() % n) as usize
+        }
+
+        fn chance(&mut self, percent: u64) -> bool {
+            self.below(100) < percent as usize
+        }
+    }
+
+    const ALPHABET: &[char] = &[
+        'a', 'b', 'Z', '0', '7', '-', '_', '.', '/', ' ', '\t', '\'', '"', '\\', '$', '|', ';',
+        '&', '<', '>', '(', ')', '!', '*', '~', '#', '{', '}', '=', 'é',
+    ];
+
+    fn literal(rng: &mut Rng) -> String {
+        let len = 1 + rng.below(6);
+        (0..len)
+            .map(|_| ALPHABET[rng.below(ALPHABET.len() as u64)])
+            .collect()
+    }
+
+    fn parameter(rng: &mut Rng) -> WordPart {
+        let names = ["HOME", "x", "_a1", "1", "0", "@", "*", "#", "?", "$"];
+        WordPart::Parameter {
+            name: names[rng.below(names.len() as u64)].to_string(),
+            quoted: rng.chance(50),
+        }
+    }
+
+    /// Generates words in the canonical shape the parser produces: no two
+    /// literals in a row, and process substitutions standing alone.
+    fn word(rng: &mut Rng, depth: usize) -> Word {
+        if depth < 2 && rng.chance(10) {
+            let kind = if rng.chance(50) {
+                ProcessSubstitutionKind::Input
+            } else {
+                ProcessSubstitutionKind::Output
+            };
+            return Word {
+                parts: vec![WordPart::ProcessSubstitution(ProcessSubstitution {
+                    kind,
+                    pipelines: pipelines(rng, depth + 1),
+                })],
+            };
+        }
+        if rng.chance(5) {
+            return Word::literal(String::new());
+        }
+
+        let mut parts = Vec::new();
+        for _ in 0..1 + rng.below(3) {
+            let after_literal = matches!(parts.last(), Some(WordPart::Literal(_)));
+            if after_literal || rng.chance(40) {
+                parts.push(parameter(rng));
+            } else {
+                parts.push(WordPart::Literal(literal(rng)));
+            }
+        }
+        Word { parts }
+    }
+
+    fn redirection(rng: &mut Rng, depth: usize) -> Redirection {
+        let target = word(rng, depth);
+        let kind = match rng.below(3) {
+            0 => RedirectionKind::Input,
+            1 => RedirectionKind::Output,
+            _ => RedirectionKind::Append,
+        };
+        Redirection {
+            path: target.literal_text(),
+            target,
+            fd: rng.below(10) as i32,
+            kind,
+        }
+    }
+
+    /// Words inside `[[ ]]` keep quoted text apart from unquoted text, and
+    /// unquoted text is limited to what cannot be mistaken for an operator.
+    fn conditional_word(rng: &mut Rng) -> Word {
+        const UNQUOTED: &[char] = &['a', 'Z', '0', '*', '?', '.', '_', '/', '^'];
+        let mut parts: Vec<WordPart> = Vec::new();
+        for _ in 0..1 + rng.below(3) {
+            let part = match rng.below(3) {
+                0 if !matches!(parts.last(), Some(WordPart::Literal(_))) => WordPart::Literal(
+                    (0..1 + rng.below(4))
+                        .map(|_| UNQUOTED[rng.below(UNQUOTED.len() as u64)])
+                        .collect(),
+                ),
+                1 if !matches!(parts.last(), Some(WordPart::Quoted(_))) => {
+                    WordPart::Quoted(literal(rng))
+                }
+                _ => parameter(rng),
+            };
+            parts.push(part);
+        }
+        Word { parts }
+    }
+
+    fn conditional(rng: &mut Rng, depth: usize) -> ConditionalExpression {
+        let choice = if depth > 2 {
+            rng.below(3)
+        } else {
+            rng.below(6)
+        };
+        let operator = |rng: &mut Rng, operators: &[&str]| {
+            operators[rng.below(operators.len() as u64)].to_string()
+        };
+        match choice {
+            0 => ConditionalExpression::Word(conditional_word(rng)),
+            1 => ConditionalExpression::Unary {
+                operator: operator(rng, UNARY_OPERATORS),
+                operand: conditional_word(rng),
+            },
+            2 => ConditionalExpression::Binary {
+                operator: operator(rng, BINARY_OPERATORS),
+                left: conditional_word(rng),
+                right: conditional_word(rng),
+            },
+            3 => ConditionalExpression::Not(Box::new(conditional(rng, depth + 1))),
+            4 => ConditionalExpression::And(
+                Box::new(conditional(rng, depth + 1)),
+                Box::new(conditional(rng, depth + 1)),
+            ),
+            _ => ConditionalExpression::Or(
+                Box::new(conditional(rng, depth + 1)),
+                Box::new(conditional(rng, depth + 1)),
+            ),
+        }
+    }
+
+    fn command(rng: &mut Rng, depth: usize) -> Command {
+        if rng.chance(10) {
+            return Command {
+                assignments: Vec::new(),
+                words: Vec::new(),
+                redirections: Vec::new(),
+                conditional: Some(conditional(rng, 0)),
+            };
+        }
+
+        // Assignment values are read as plain words, without process
+        // substitutions.
+        let count = if rng.chance(30) { 1 + rng.below(2) } else { 0 };
+        let assignments: Vec<Assignment> = (0..count)
+            .map(|_| Assignment {
+                name: ["x", "_a1", "HOME"][rng.below(3)].to_string(),
+                value: if rng.chance(20) {
+                    Word { parts: Vec::new() }
+                } else {
+                    word(rng, 2)
+                },
+            })
+            .collect();
+        let mut words: Vec<Word> = (0..rng.below(4)).map(|_| word(rng, depth)).collect();
+        if words.is_empty() && (assignments.is_empty() || rng.chance(50)) {
+            words.push(word(rng, depth));
+        }
+        if !words.is_empty() && rng.chance(10) {
+            let reserved = ["!", "time", "-p", "[["];
+            words[0] = Word::literal(reserved[rng.below(reserved.len() as u64)].to_string());
+        }
+
+        let redirections = (0..rng.below(4)).map(|_| redirection(rng, depth)).collect();
+
+        Command {
+            assignments,
+            words,
+            redirections,
+            conditional: None,
+        }
+    }
+
+    fn pipelines(rng: &mut Rng, depth: usize) -> Vec<Pipeline> {
+        let mut pipelines: Vec<Pipeline> = Vec::new();
+        for _ in 0..1 + rng.below(3) {
+            // `&` ends an and-or list, so only a foreground pipeline can be
+            // followed by `&&` or `||`.
+            let joined = pipelines.last().is_some_and(|last| !last.background);
+            let and_or = match rng.below(4) {
+                0 if joined => Some(AndOr::And),
+                1 if joined => Some(AndOr::Or),
+                _ => None,
+            };
+            let time = match rng.below(6) {
+                0 => Some(TimeFormat::Variable),
+                1 => Some(TimeFormat::Posix),
+                _ => None,
+            };
+            let commands = if time.is_some() && rng.chance(10) {
+                Vec::new()
+            } else {
+                (0..1 + rng.below(3)).map(|_| command(rng, depth)).collect()
+            };
+            pipelines.push(Pipeline {
+                and_or,
+                commands,
+                negated: rng.chance(20),
+                time,
+                background: rng.chance(20),
+            });
+        }
+        pipelines
+    }
+
+    #[test]
+    fn test_print_is_canonical_shell() {
+        let ast = parse_tokens(
+            Token::tokenize(
+                r#"time -p ! echo "$HOME"/x 'a b' 2>> log | cat <(ls) & true&&false||x;y"#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            print_pipelines(&ast),
+            r#"time -p ! echo "${HOME}"/x 'a b' 2>> log | cat <(ls) & true && false || x; y"#
+        );
+    }
+
+    #[test]
+    fn test_random_asts_round_trip() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2000 {
+            let ast = pipelines(&mut rng, 0);
+            let printed = print_pipelines(&ast);
+            let reparsed = Token::tokenize(&printed)
+                .and_then(parse_tokens)
+                .unwrap_or_else(|e| panic!("{:?} failed to parse: {}", printed, e));
+            assert_eq!(reparsed, ast, "{:?} did not round-trip", printed);
+        }
+    }
+}
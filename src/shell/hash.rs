@@ -0,0 +1,145 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+/// Whether `path` is a regular file that someone may execute.
+pub fn is_executable_file(path: &Path) -> bool {
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Finds `name` in the directories of `path`, which is a `PATH` value. An
+/// empty directory means the current one.
+pub fn search_path(name: &str, path: &str) -> Option<PathBuf> {
+    env::split_paths(path)
+        .map(|dir| {
+            if dir.as_os_str().is_empty() {
+                PathBuf::from(name)
+            } else {
+                dir.join(name)
+            }
+        })
+        .find(|candidate| is_executable_file(candidate))
+}
+
+/// Every match for `name` in the directories of `path`, in order.
+pub fn search_path_all(name: &str, path: &str) -> Vec<PathBuf> {
+    env::split_paths(path)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(|dir| dir.join(name))
+        .filter(|candidate| is_executable_file(candidate))
+        .collect()
+}
+
+/// The `PATH` that finds the standard utilities, for `command -p`.
+pub fn default_path() -> String {
+    let mut buffer = [0u8; 256];
+    let length = unsafe { libc::confstr(libc::_CS_PATH, buffer.as_mut_ptr().cast(), buffer.len()) };
+    if length == 0 || length > buffer.len() {
+        return "/bin:/usr/bin".to_string();
+    }
+    String::from_utf8_lossy(&buffer[..length - 1]).into_owned()
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// How many times the command has been run from this entry.
+    pub hits: usize,
+}
+
+/// Where commands were found on `PATH`, so that running one again does not
+/// search the directories again. The table empties itself when `PATH`
+/// changes.
+#[derive(Debug, Default)]
+pub struct CommandHash {
+    /// The `PATH` the entries were found with.
+    path: String,
+    entries: BTreeMap<String, Entry>,
+}
+
+impl CommandHash {
+    /// Finds a command through the table, searching `path` if it is not
+    /// there or its file has gone.
+    pub fn find(&mut self, name: &str, path: &str) -> Option<&mut Entry> {
+        self.follow(path);
+        if self
+            .entries
+            .get(name)
+            .is_none_or(|entry| !is_executable_file(&entry.path))
+        {
+            let found = search_path(name, path)?;
+            self.entries.insert(
+                name.to_string(),
+                Entry {
+                    path: found,
+                    hits: 0,
+                },
+            );
+        }
+        self.entries.get_mut(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.get(name)
+    }
+
+    /// `hash -p`: remembers `path` for `name` without searching. The entry
+    /// lasts until `search`, the current `PATH`, changes.
+    pub fn insert(&mut self, name: &str, path: PathBuf, search: &str) {
+        self.follow(search);
+        self.entries
+            .insert(name.to_string(), Entry { path, hits: 0 });
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.entries.remove(name).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &Entry)> {
+        self.entries.iter()
+    }
+
+    /// Empties the table if `path` is not the `PATH` its entries belong to.
+    fn follow(&mut self, path: &str) {
+        if self.path != path {
+            self.entries.clear();
+            self.path = path.to_string();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CommandHash;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_caches_until_path_changes() {
+        let mut hash = CommandHash::default();
+        let path = hash.find("sh", "/nonexistent:/bin").unwrap().path.clone();
+        assert_eq!(path, PathBuf::from("/bin/sh"));
+        assert!(hash.get("sh").is_some());
+
+        assert!(hash.find("sh", "/nonexistent").is_none());
+        assert!(hash.get("sh").is_none());
+    }
+
+    #[test]
+    fn test_remembers_set_paths() {
+        let mut hash = CommandHash::default();
+        hash.insert("myls", PathBuf::from("/bin/ls"), "/usr/bin:/bin");
+        let entry = hash.find("myls", "/usr/bin:/bin").unwrap();
+        assert_eq!(entry.path, PathBuf::from("/bin/ls"));
+
+        assert!(hash.find("myls", "/bin").is_none());
+    }
+}
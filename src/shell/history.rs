@@ -0,0 +1,500 @@
+use rustyline::history::{History, SearchDirection};
+
+use crate::error::ShellError;
+
+/// The result of running history expansion over an input line.
+#[derive(Debug, PartialEq)]
+pub struct HistoryExpansion {
+    pub line: String,
+    /// Whether any `!` or `^` expansion took place.
+    pub expanded: bool,
+    /// Set by the `:p` modifier: the line is shown and saved but not run.
+    pub print_only: bool,
+}
+
+/// Copies the entries out of the line editor's history, oldest first.
+pub fn history_entries(history: &dyn History) -> Vec<String> {
+    (0..history.len())
+        .filter_map(|index| {
+            history
+                .get(index, SearchDirection::Forward)
+                .ok()
+                .flatten()
+                .map(|result| result.entry.into_owned())
+        })
+        .collect()
+}
+
+/// Performs csh-style history expansion on `line` against `entries`, the
+/// history oldest first. Event `!n` refers to `entries[n - 1]`.
+pub fn expand_history(line: &str, entries: &[String]) -> Result<HistoryExpansion, ShellError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut expander = Expander {
+        chars: &chars,
+        pos: 0,
+        entries,
+        print_only: false,
+    };
+
+    let mut output = String::new();
+    let mut expanded = false;
+
+    if chars.first() == Some(&'^') {
+        output.push_str(&expander.quick_substitution()?);
+        expanded = true;
+    }
+
+    let mut in_double_quotes = false;
+    while let Some(&c) = expander.peek() {
+        match c {
+            '\\' => {
+                output.push(c);
+                expander.pos += 1;
+                if let Some(&escaped) = expander.peek() {
+                    output.push(escaped);
+                    expander.pos += 1;
+                }
+            }
+            '\'' if !in_double_quotes => {
+                output.push(c);
+                expander.pos += 1;
+                while let Some(&ch) = expander.peek() {
+                    output.push(ch);
+                    expander.pos += 1;
+                    if ch == '\'' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                in_double_quotes = !in_double_quotes;
+                output.push(c);
+                expander.pos += 1;
+            }
+            // `$!` and `${!name}` are parameter expansions.
+            '!' if output.ends_with('$') || output.ends_with("${") => {
+                output.push(c);
+                expander.pos += 1;
+            }
+            '!' if starts_reference(chars.get(expander.pos + 1), in_double_quotes) => {
+                expander.pos += 1;
+                let substitution = expander.history_reference(&output)?;
+                output.push_str(&substitution);
+                expanded = true;
+            }
+            _ => {
+                output.push(c);
+                expander.pos += 1;
+            }
+        }
+    }
+
+    Ok(HistoryExpansion {
+        line: output,
+        expanded,
+        print_only: expander.print_only,
+    })
+}
+
+/// Whether a `!` followed by `next` begins a history reference. Like bash, a
+/// `!` before a blank, `=`, `(` or a closing double quote stays literal.
+fn starts_reference(next: Option<&char>, in_double_quotes: bool) -> bool {
+    match next {
+        None | Some(' ' | '\t' | '\n' | '=' | '(') => false,
+        Some('"') => !in_double_quotes,
+        Some(_) => true,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Bound {
+    Index(usize),
+    Last,
+    /// `x-`: everything up to, but not including, the last word.
+    BeforeLast,
+}
+
+struct Expander<'a> {
+    chars: &'a [char],
+    pos: usize,
+    entries: &'a [String],
+    print_only: bool,
+}
+
+impl Expander<'_> {
+    fn peek(&self) -> Option<&char> {
+        self.chars.get(self.pos)
+    }
+
+    fn next_if(&mut self, expected: char) -> bool {
+        if self.peek() == Some(&expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn read_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let mut text = String::new();
+        while let Some(&c) = self.peek() {
+            if !predicate(c) {
+                break;
+            }
+            text.push(c);
+            self.pos += 1;
+        }
+        text
+    }
+
+    fn last_event(&self) -> Result<String, ShellError> {
+        self.entries
+            .last()
+            .cloned()
+            .ok_or_else(|| ShellError::InternalError("!!: event not found".to_string()))
+    }
+
+    /// `^old^new^`, shorthand for `!!:s/old/new/`.
+    fn quick_substitution(&mut self) -> Result<String, ShellError> {
+        self.pos += 1;
+        let old = self.read_while(|c| c != '^');
+        self.next_if('^');
+        let new = self.read_while(|c| c != '^');
+        self.next_if('^');
+
+        let event = self.last_event()?;
+        if !event.contains(&old) {
+            return Err(ShellError::InternalError(
+                "^: substitution failed".to_string(),
+            ));
+        }
+        let text = event.replacen(&old, &new, 1);
+        self.apply_modifiers(text)
+    }
+
+    /// Expands the history reference that follows a `!`: an event, then an
+    /// optional word designator and modifiers. `line_so_far` serves `!#`.
+    fn history_reference(&mut self, line_so_far: &str) -> Result<String, ShellError> {
+        let start = self.pos - 1;
+        let event = match self.peek() {
+            Some('!') => {
+                self.pos += 1;
+                self.last_event()?
+            }
+            Some('#') => {
+                self.pos += 1;
+                line_so_far.to_string()
+            }
+            Some('^' | '$' | '*' | '%' | ':') => self.last_event()?,
+            Some('?') => {
+                self.pos += 1;
+                let needle = self.read_while(|c| c != '?' && c != '\n');
+                self.next_if('?');
+                self.find_event(|entry| entry.contains(&needle), start)?
+            }
+            Some(&c) if c == '-' || c.is_ascii_digit() => {
+                let negative = self.next_if('-');
+                let digits = self.read_while(|c| c.is_ascii_digit());
+                let n: usize = digits.parse().map_err(|_| self.not_found(start))?;
+                let index = if negative {
+                    self.entries.len().checked_sub(n)
+                } else {
+                    n.checked_sub(1)
+                };
+                index
+                    .and_then(|index| self.entries.get(index))
+                    .cloned()
+                    .ok_or_else(|| self.not_found(start))?
+            }
+            _ => {
+                let prefix = self.read_while(|c| {
+                    !c.is_whitespace() && !matches!(c, ':' | '"' | '\'' | ';' | '|' | '&')
+                });
+                if prefix.is_empty() {
+                    return Err(self.not_found(start));
+                }
+                self.find_event(|entry| entry.starts_with(&prefix), start)?
+            }
+        };
+
+        let text = self.word_designator(&event, start)?;
+        self.apply_modifiers(text)
+    }
+
+    fn find_event(
+        &self,
+        matches: impl Fn(&str) -> bool,
+        start: usize,
+    ) -> Result<String, ShellError> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|entry| matches(entry))
+            .cloned()
+            .ok_or_else(|| self.not_found(start))
+    }
+
+    fn not_found(&self, start: usize) -> ShellError {
+        let reference: String = self.chars[start..self.pos].iter().collect();
+        ShellError::InternalError(format!("{}: event not found", reference))
+    }
+
+    fn word_designator(&mut self, event: &str, start: usize) -> Result<String, ShellError> {
+        let after_colon = self.peek() == Some(&':')
+            && matches!(self.chars.get(self.pos + 1), Some(c) if c.is_ascii_digit() || "^$*-%".contains(*c));
+        let bare = matches!(self.peek(), Some('^' | '$' | '*' | '%'));
+        if !after_colon && !bare {
+            return Ok(event.to_string());
+        }
+        if after_colon {
+            self.pos += 1;
+        }
+
+        let words = split_words(event);
+        let (first, last) = match self.peek() {
+            Some('*') => {
+                self.pos += 1;
+                if words.len() < 2 {
+                    return Ok(String::new());
+                }
+                (Bound::Index(1), Bound::Last)
+            }
+            Some('-') => (Bound::Index(0), self.range_end()),
+            _ => {
+                let first = match self.peek() {
+                    Some('^') => {
+                        self.pos += 1;
+                        Bound::Index(1)
+                    }
+                    Some('$') => {
+                        self.pos += 1;
+                        Bound::Last
+                    }
+                    _ => {
+                        let digits = self.read_while(|c| c.is_ascii_digit());
+                        match digits.parse() {
+                            Ok(n) => Bound::Index(n),
+                            Err(_) => return Err(self.bad_word_specifier(start)),
+                        }
+                    }
+                };
+                match self.peek() {
+                    Some('*') => {
+                        self.pos += 1;
+                        (first, Bound::Last)
+                    }
+                    Some('-') => (first, self.range_end()),
+                    _ => (first, first),
+                }
+            }
+        };
+
+        let resolve = |bound: Bound| match bound {
+            Bound::Index(n) => Some(n),
+            Bound::Last => words.len().checked_sub(1),
+            Bound::BeforeLast => words.len().checked_sub(2),
+        };
+        match (resolve(first), resolve(last)) {
+            (Some(first), Some(last)) if first <= last && last < words.len() => {
+                Ok(words[first..=last].join(" "))
+            }
+            _ => Err(self.bad_word_specifier(start)),
+        }
+    }
+
+    /// Parses the `-y` part of a `x-y` range, with the `-` still pending.
+    fn range_end(&mut self) -> Bound {
+        self.pos += 1;
+        if self.next_if('$') {
+            return Bound::Last;
+        }
+        let digits = self.read_while(|c| c.is_ascii_digit());
+        match digits.parse() {
+            Ok(n) => Bound::Index(n),
+            Err(_) => Bound::BeforeLast,
+        }
+    }
+
+    fn bad_word_specifier(&self, start: usize) -> ShellError {
+        let reference: String = self.chars[start..self.pos].iter().collect();
+        ShellError::InternalError(format!("{}: bad word specifier", reference))
+    }
+
+    fn apply_modifiers(&mut self, mut text: String) -> Result<String, ShellError> {
+        while self.peek() == Some(&':') {
+            let Some(&modifier) = self.chars.get(self.pos + 1) else {
+                break;
+            };
+            match modifier {
+                'h' => {
+                    if let Some((head, _)) = text.rsplit_once('/') {
+                        text = head.to_string();
+                    }
+                }
+                't' => {
+                    if let Some((_, tail)) = text.rsplit_once('/') {
+                        text = tail.to_string();
+                    }
+                }
+                'r' => {
+                    if let Some((stem, _)) = text.rsplit_once('.')
+                        && !stem.ends_with('/')
+                        && !text[stem.len()..].contains('/')
+                    {
+                        text = stem.to_string();
+                    }
+                }
+                'e' => {
+                    text = match text.rfind('.') {
+                        Some(dot) if !text[dot..].contains('/') => text[dot..].to_string(),
+                        _ => String::new(),
+                    };
+                }
+                'p' => self.print_only = true,
+                'q' => text = format!("'{}'", text.replace('\'', r"'\''")),
+                's' | 'g' => {
+                    self.pos += 2;
+                    let global = modifier == 'g';
+                    if global && !self.next_if('s') {
+                        return Err(ShellError::InternalError(
+                            "history: unsupported modifier".to_string(),
+                        ));
+                    }
+                    text = self.substitute(text, global)?;
+                    continue;
+                }
+                _ => break,
+            }
+            self.pos += 2;
+        }
+        Ok(text)
+    }
+
+    /// Applies `s/old/new/` with the position just after the `s`. Any
+    /// delimiter may replace `/`, and `&` in `new` stands for `old`.
+    fn substitute(&mut self, text: String, global: bool) -> Result<String, ShellError> {
+        let Some(&delimiter) = self.peek() else {
+            return Ok(text);
+        };
+        self.pos += 1;
+        let old = self.read_while(|c| c != delimiter);
+        self.next_if(delimiter);
+        let new = self.read_while(|c| c != delimiter).replace('&', &old);
+        self.next_if(delimiter);
+
+        if old.is_empty() || !text.contains(&old) {
+            return Err(ShellError::InternalError(format!(
+                ":s{}{}{}{}: substitution failed",
+                delimiter, old, delimiter, new
+            )));
+        }
+        Ok(if global {
+            text.replace(&old, &new)
+        } else {
+            text.replacen(&old, &new, 1)
+        })
+    }
+}
+
+/// Splits a history entry into words on unquoted whitespace. Quotes are kept
+/// so that a designated word can be pasted back into a command line.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '\\' => {
+                word.push(c);
+                if let Some(escaped) = chars.next() {
+                    word.push(escaped);
+                }
+                continue;
+            }
+            None if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                continue;
+            }
+            None => {}
+        }
+        word.push(c);
+    }
+
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand_history;
+    use crate::error::ShellError;
+
+    fn expand(line: &str) -> String {
+        let entries = vec![
+            "ls /usr/lib/libfoo.so.1".to_string(),
+            "cat 'a b' c d".to_string(),
+            "echo one two three".to_string(),
+        ];
+        expand_history(line, &entries).unwrap().line
+    }
+
+    #[test]
+    fn test_event_designators() {
+        assert_eq!(expand("!!"), "echo one two three");
+        assert_eq!(expand("!1"), "ls /usr/lib/libfoo.so.1");
+        assert_eq!(expand("!-2"), "cat 'a b' c d");
+        assert_eq!(expand("!ca"), "cat 'a b' c d");
+        assert_eq!(expand("!?usr?"), "ls /usr/lib/libfoo.so.1");
+        assert_eq!(expand("^one^uno"), "echo uno two three");
+    }
+
+    #[test]
+    fn test_word_designators() {
+        assert_eq!(expand("vi !$"), "vi three");
+        assert_eq!(expand("x !^ !*"), "x one one two three");
+        assert_eq!(expand("!-2:1"), "'a b'");
+        assert_eq!(expand("!-2:2-3"), "c d");
+        assert_eq!(expand("!!:0"), "echo");
+        assert_eq!(expand("!!:1-"), "one two");
+    }
+
+    #[test]
+    fn test_modifiers() {
+        assert_eq!(expand("!1:$:h"), "/usr/lib");
+        assert_eq!(expand("!1:$:t"), "libfoo.so.1");
+        assert_eq!(expand("!1:$:t:r"), "libfoo.so");
+        assert_eq!(expand("!1:$:e"), ".1");
+        assert_eq!(expand("!!:s/two/2/"), "echo one 2 three");
+        assert_eq!(expand("!!:gs/o/0/"), "ech0 0ne tw0 three");
+    }
+
+    #[test]
+    fn test_quoting_and_literal_bangs() {
+        assert_eq!(expand("echo '!!' \\!! ! x!="), "echo '!!' \\!! ! x!=");
+        assert!(expand_history("!nothing", &[]).is_err());
+    }
+
+    #[test]
+    fn test_parameter_bangs_are_not_references() {
+        assert_eq!(expand("echo $!;"), "echo $!;");
+        assert_eq!(expand("echo ${!x} \"$!\""), "echo ${!x} \"$!\"");
+    }
+
+    #[test]
+    fn test_empty_prefix_is_not_found() {
+        let entries = vec!["echo one".to_string()];
+        assert!(matches!(
+            expand_history("!;", &entries),
+            Err(ShellError::InternalError(message)) if message == "!: event not found"
+        ));
+    }
+}
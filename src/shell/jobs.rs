@@ -0,0 +1,430 @@
+use std::{
+    fmt, io,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::process::ExitStatusExt,
+    },
+    process::ExitStatus,
+};
+
+use crate::shell::signals::{
+    JOB_CONTROL_SIGNALS, exit_status, signal_description, termination_message,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobState {
+    Running,
+    /// Stopped by the given signal.
+    Stopped(i32),
+    /// Finished with the given exit status, or with 128+N if killed by
+    /// signal N.
+    Done(i32),
+}
+
+impl JobState {
+    fn from_wait(status: libc::c_int) -> Self {
+        if libc::WIFSTOPPED(status) {
+            JobState::Stopped(libc::WSTOPSIG(status))
+        } else if libc::WIFCONTINUED(status) {
+            JobState::Running
+        } else {
+            JobState::Done(exit_status(status))
+        }
+    }
+
+    /// How `jobs` and job notices describe the state.
+    fn describe(&self) -> String {
+        match *self {
+            JobState::Running => "Running".to_string(),
+            JobState::Stopped(signal) => signal_description(signal),
+            JobState::Done(0) => "Done".to_string(),
+            JobState::Done(status) if status > 128 => signal_description(status - 128),
+            JobState::Done(status) => format!("Exit {}", status),
+        }
+    }
+}
+
+/// A process of a job. A stage that never started has no pid and already has
+/// its status.
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub pid: libc::pid_t,
+    pub status: Option<i32>,
+    /// Set if a signal ended the process; see `termination_message`.
+    pub termination: Option<String>,
+}
+
+impl Process {
+    pub fn running(pid: libc::pid_t) -> Self {
+        Process {
+            pid,
+            status: None,
+            termination: None,
+        }
+    }
+
+    pub fn finished(status: i32) -> Self {
+        Process {
+            pid: 0,
+            status: Some(status),
+            termination: None,
+        }
+    }
+}
+
+/// A pipeline started with `&`, or a foreground pipeline of a shell with job
+/// control. Its processes share the process group `pgid`.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: usize,
+    pub pgid: libc::pid_t,
+    pub processes: Vec<Process>,
+    pub command: String,
+    pub state: JobState,
+    /// The terminal modes the job had when it was stopped, restored when it
+    /// is brought back to the foreground.
+    pub modes: Option<libc::termios>,
+}
+
+impl Job {
+    /// The status of each process, in pipeline order.
+    pub fn statuses(&self) -> Vec<i32> {
+        self.processes
+            .iter()
+            .map(|process| process.status.unwrap_or(0))
+            .collect()
+    }
+
+    /// Waits for one of the job's processes to change state and records it.
+    /// Returns false if nothing changed, which with `WNOHANG` means there is
+    /// nothing to report yet.
+    fn wait_once(&mut self, flags: libc::c_int) -> bool {
+        let mut status = 0;
+        let pid = loop {
+            let pid = unsafe { libc::waitpid(-self.pgid, &mut status, flags) };
+            if pid != -1 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                break pid;
+            }
+        };
+
+        if pid == -1 {
+            // Reaped elsewhere; the statuses are gone.
+            for process in &mut self.processes {
+                process.status.get_or_insert(127);
+            }
+            self.state = JobState::Done(127);
+            return false;
+        }
+        if pid == 0 {
+            return false;
+        }
+
+        match JobState::from_wait(status) {
+            JobState::Done(exit) => {
+                if let Some(process) = self.processes.iter_mut().find(|p| p.pid == pid) {
+                    process.status = Some(exit);
+                    process.termination = termination_message(&ExitStatus::from_raw(status));
+                }
+                if let [.., last] = self.processes.as_slice()
+                    && self.processes.iter().all(|p| p.status.is_some())
+                {
+                    self.state = JobState::Done(last.status.unwrap());
+                }
+            }
+            state => self.state = state,
+        }
+        true
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct JobTable {
+    /// Ordered by job number.
+    jobs: Vec<Job>,
+    /// Job numbers from least to most recently made current; the last is the
+    /// current job, `%+`, and the one before it the previous job, `%-`.
+    recent: Vec<usize>,
+}
+
+impl JobTable {
+    pub fn add(&mut self, pgid: libc::pid_t, processes: Vec<Process>, command: String) -> usize {
+        let id = self.jobs.last().map_or(1, |job| job.id + 1);
+        self.jobs.push(Job {
+            id,
+            pgid,
+            processes,
+            command,
+            state: JobState::Running,
+            modes: None,
+        });
+        self.recent.push(id);
+        id
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    pub fn find_pid(&self, pid: libc::pid_t) -> Option<usize> {
+        self.jobs
+            .iter()
+            .find(|job| job.processes.iter().any(|process| process.pid == pid))
+            .map(|job| job.id)
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        self.recent.retain(|&recent| recent != id);
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    pub fn clear(&mut self) {
+        self.jobs.clear();
+        self.recent.clear();
+    }
+
+    pub fn make_current(&mut self, id: usize) {
+        self.recent.retain(|&recent| recent != id);
+        self.recent.push(id);
+    }
+
+    /// `+` for the current job, `-` for the previous one.
+    fn mark(&self, id: usize) -> char {
+        match self.recent.iter().rev().position(|&recent| recent == id) {
+            Some(0) => '+',
+            Some(1) => '-',
+            _ => ' ',
+        }
+    }
+
+    /// Resolves a job specification: `%n`, `%%`, `%+`, `%-`, `%prefix` or
+    /// `%?substring`.
+    pub fn resolve(&self, spec: &str) -> Result<usize, String> {
+        let no_such_job = || format!("{}: no such job", spec);
+        let Some(rest) = spec.strip_prefix('%') else {
+            return Err(no_such_job());
+        };
+
+        let id = match rest {
+            "" | "%" | "+" => self.recent.last().copied(),
+            "-" => self.recent.iter().rev().nth(1).copied(),
+            _ if rest.chars().all(|c| c.is_ascii_digit()) => {
+                rest.parse().ok().filter(|&id| self.get(id).is_some())
+            }
+            _ => {
+                let matches: Vec<usize> = self
+                    .jobs
+                    .iter()
+                    .filter(|job| match rest.strip_prefix('?') {
+                        Some(substring) => job.command.contains(substring),
+                        None => job.command.starts_with(rest),
+                    })
+                    .map(|job| job.id)
+                    .collect();
+                if matches.len() > 1 {
+                    return Err(format!("{}: ambiguous job spec", spec));
+                }
+                matches.first().copied()
+            }
+        };
+        id.ok_or_else(no_such_job)
+    }
+
+    /// Collects state changes of every job without blocking.
+    pub fn update(&mut self) {
+        let flags = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+        for job in &mut self.jobs {
+            while !matches!(job.state, JobState::Done(_)) && job.wait_once(flags) {}
+        }
+    }
+
+    /// Blocks until the job finishes or stops, and returns its new state.
+    pub fn wait(&mut self, id: usize) -> JobState {
+        let Some(job) = self.get_mut(id) else {
+            return JobState::Done(127);
+        };
+        while job.state == JobState::Running && job.wait_once(libc::WUNTRACED) {}
+        job.state
+    }
+
+    /// Formats a job the way `jobs` lists it, with its pid if `long`.
+    pub fn format(&self, job: &Job, long: bool) -> String {
+        let pid = if long {
+            format!("{} ", job.pgid)
+        } else {
+            " ".to_string()
+        };
+        let background = if job.state == JobState::Running {
+            " &"
+        } else {
+            ""
+        };
+        format!(
+            "[{}]{} {}{:<24}{}{}",
+            job.id,
+            self.mark(job.id),
+            pid,
+            job.state.describe(),
+            job.command,
+            background
+        )
+    }
+
+    /// Removes the jobs that have finished, returning a notice for each.
+    pub fn take_finished(&mut self) -> Vec<String> {
+        let finished: Vec<usize> = self
+            .jobs
+            .iter()
+            .filter(|job| matches!(job.state, JobState::Done(_)))
+            .map(|job| job.id)
+            .collect();
+        finished
+            .into_iter()
+            .map(|id| {
+                let notice = self.format(self.get(id).unwrap(), false);
+                self.remove(id);
+                notice
+            })
+            .collect()
+    }
+}
+
+/// The controlling terminal of an interactive shell, which the shell hands to
+/// each foreground job and takes back when the job finishes or stops.
+pub struct Terminal {
+    fd: OwnedFd,
+    pgid: libc::pid_t,
+    /// The shell's own terminal modes, restored whenever it takes the
+    /// terminal back.
+    modes: libc::termios,
+}
+
+impl fmt::Debug for Terminal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Terminal")
+            .field("fd", &self.fd)
+            .field("pgid", &self.pgid)
+            .finish()
+    }
+}
+
+impl Terminal {
+    /// Takes control of the terminal on standard input, if there is one: the
+    /// shell waits until it is in the foreground, then leads its own process
+    /// group and makes that the terminal's foreground group.
+    pub fn acquire() -> Option<Self> {
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) == 0 {
+                return None;
+            }
+            while libc::tcgetpgrp(libc::STDIN_FILENO) != libc::getpgrp() {
+                libc::kill(-libc::getpgrp(), libc::SIGTTIN);
+            }
+
+            // Taking the terminal back from a job is done from the
+            // background, which would otherwise stop the shell.
+            for &signal in JOB_CONTROL_SIGNALS {
+                libc::signal(signal, libc::SIG_IGN);
+            }
+
+            let fd = libc::fcntl(libc::STDIN_FILENO, libc::F_DUPFD_CLOEXEC, 10);
+            if fd == -1 {
+                return None;
+            }
+            let fd = OwnedFd::from_raw_fd(fd);
+            // Fails harmlessly if the shell already leads its session.
+            libc::setpgid(0, 0);
+            let pgid = libc::getpgrp();
+            libc::tcsetpgrp(fd.as_raw_fd(), pgid);
+
+            let mut modes = std::mem::zeroed();
+            libc::tcgetattr(fd.as_raw_fd(), &mut modes);
+            Some(Terminal { fd, pgid, modes })
+        }
+    }
+
+    /// The descriptor a child uses to put its process group in the
+    /// foreground before it execs.
+    pub fn fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    /// Puts a job's process group in the foreground, restoring the modes it
+    /// had when it stopped.
+    pub fn give_to(&self, pgid: libc::pid_t, modes: Option<&libc::termios>) {
+        unsafe {
+            if let Some(modes) = modes {
+                libc::tcsetattr(self.fd(), libc::TCSADRAIN, modes);
+            }
+            libc::tcsetpgrp(self.fd(), pgid);
+        }
+    }
+
+    /// Puts the shell back in the foreground with its own modes, returning
+    /// the modes the job left the terminal in.
+    pub fn reclaim(&self) -> libc::termios {
+        unsafe {
+            let mut modes = std::mem::zeroed();
+            libc::tcgetattr(self.fd(), &mut modes);
+            libc::tcsetpgrp(self.fd(), self.pgid);
+            libc::tcsetattr(self.fd(), libc::TCSADRAIN, &self.modes);
+            modes
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JobState, JobTable, Process};
+
+    #[test]
+    fn test_resolves_job_specs() {
+        let mut table = JobTable::default();
+        for (pid, command) in [(100, "sleep 10"), (200, "make all"), (300, "sleep 20")] {
+            table.add(pid, vec![Process::running(pid)], command.to_string());
+        }
+
+        assert_eq!(table.resolve("%%"), Ok(3));
+        assert_eq!(table.resolve("%-"), Ok(2));
+        assert_eq!(table.resolve("%1"), Ok(1));
+        assert_eq!(table.resolve("%make"), Ok(2));
+        assert_eq!(table.resolve("%?20"), Ok(3));
+        assert_eq!(
+            table.resolve("%sleep"),
+            Err("%sleep: ambiguous job spec".to_string())
+        );
+        assert_eq!(table.resolve("%4"), Err("%4: no such job".to_string()));
+
+        table.make_current(1);
+        assert_eq!(table.resolve("%+"), Ok(1));
+        assert_eq!(table.resolve("%-"), Ok(3));
+    }
+
+    #[test]
+    fn test_formats_jobs_like_bash() {
+        let mut table = JobTable::default();
+        let id = table.add(100, vec![Process::running(100)], "sleep 10".to_string());
+        let job = table.get(id).unwrap();
+        assert_eq!(
+            table.format(job, false),
+            "[1]+  Running                 sleep 10 &"
+        );
+
+        table.get_mut(id).unwrap().state = JobState::Done(2);
+        let job = table.get(id).unwrap();
+        assert_eq!(
+            table.format(job, true),
+            "[1]+ 100 Exit 2                  sleep 10"
+        );
+        assert_eq!(table.take_finished().len(), 1);
+        assert!(table.jobs().is_empty());
+    }
+}
@@ -1,19 +1,44 @@
 use rustyline::{
-    Cmd, Config, Editor, KeyEvent, completion::FilenameCompleter, history::DefaultHistory,
+    Cmd, Config, Editor, KeyEvent, completion::FilenameCompleter, error::ReadlineError,
+    history::DefaultHistory,
 };
 
+pub mod hash;
+pub mod history;
+pub mod jobs;
+pub mod signals;
+pub mod traps;
+
 #[cfg(unix)]
 use std::path::Path;
-use std::{collections::HashSet, env};
+use std::path::PathBuf;
+use std::{
+    collections::HashSet,
+    env,
+    io::{self, Write},
+    process,
+};
 
 use crate::{
     completer::MyHelper,
     error::ShellError,
     executor::execute_pipeline,
-    parser::{ast::Pipeline, lexer::Token, parse_tokens},
+    parser::{
+        ast::{AndOr, Pipeline},
+        incremental::{ParseStatus, Parser},
+    },
+    shell::{
+        hash::CommandHash,
+        history::{expand_history, history_entries},
+        jobs::{JobTable, Terminal},
+        signals::{
+            INTERACTIVE_SIGNALS, JOB_CONTROL_SIGNALS, ignore_interactive_signals, take_pending,
+        },
+        traps::{Condition, Traps},
+    },
 };
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::{self},
 };
 
@@ -22,6 +47,48 @@ pub struct Shell {
     pub environment_var: HashMap<String, String>,
     config: Config,
     pub command_names: Vec<String>,
+    /// `$0`.
+    pub shell_name: String,
+    /// `$1`, `$2`, ... and the values of `$@` and `$*`.
+    pub positional_params: Vec<String>,
+    pub aliases: BTreeMap<String, String>,
+    /// Indexed array variables, such as `BASH_REMATCH`.
+    pub arrays: HashMap<String, Vec<String>>,
+    /// `--posix` or `set -o posix`: reject bash extensions and follow POSIX
+    /// in the builtins.
+    pub posix: bool,
+    /// Whether commands are read from the line editor rather than a `-c`
+    /// string or script.
+    pub interactive: bool,
+    /// `set -o pipefail`: a pipeline's status is that of its last failing
+    /// stage rather than of its last stage.
+    pub pipefail: bool,
+    /// `set -e`: exit when a command fails, unless its status is inverted
+    /// with `!`.
+    pub errexit: bool,
+    /// `set -u`: expanding an unset parameter is an error.
+    pub nounset: bool,
+    /// `set -x`: print each command, expanded and prefixed by `$PS4`, before
+    /// running it.
+    pub xtrace: bool,
+    /// `set -v`: print input lines as they are read.
+    pub verbose: bool,
+    /// `$?`.
+    pub last_status: i32,
+    /// Pipelines started with `&`.
+    pub jobs: JobTable,
+    /// The controlling terminal, when the shell is interactive and has job
+    /// control: each pipeline then gets its own process group and the
+    /// terminal while it runs.
+    pub terminal: Option<Terminal>,
+    /// Actions set with `trap`.
+    pub traps: Traps,
+    /// `$LINENO`: the line of the script or session being run.
+    pub line_number: usize,
+    /// Where commands have been found on `PATH`.
+    pub hash: CommandHash,
+    /// `$!`: the process id of the most recent background job.
+    pub last_background_pid: Option<libc::pid_t>,
 }
 
 impl Shell {
@@ -30,6 +97,24 @@ impl Shell {
             environment_var: HashMap::new(),
             config,
             command_names: Vec::new(),
+            shell_name: env::args().next().unwrap_or_default(),
+            positional_params: Vec::new(),
+            aliases: BTreeMap::new(),
+            arrays: HashMap::new(),
+            posix: false,
+            interactive: false,
+            pipefail: false,
+            errexit: false,
+            nounset: false,
+            xtrace: false,
+            verbose: false,
+            last_status: 0,
+            jobs: JobTable::default(),
+            terminal: None,
+            traps: Traps::default(),
+            line_number: 0,
+            hash: CommandHash::default(),
+            last_background_pid: None,
         };
 
         shell.command_names = shell.collect_command_names();
@@ -67,23 +152,188 @@ impl Shell {
         vec
     }
 
+    /// Looks a variable up in the shell's own variables, then the environment.
+    pub fn get_var(&self, name: &str) -> Option<String> {
+        self.environment_var
+            .get(name)
+            .cloned()
+            .or_else(|| env::var(name).ok())
+    }
+
+    pub fn set_var(&mut self, name: &str, value: String) {
+        self.environment_var.insert(name.to_string(), value);
+    }
+
+    /// The shell variables that came from the environment, with their
+    /// current values, to pass on to the commands the shell runs.
+    pub fn exported_vars(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.environment_var
+            .iter()
+            .filter(|(name, _)| env::var_os(name).is_some())
+    }
+
+    /// Resolves a command name to the file to run: a name with a slash as it
+    /// is, any other through the hash table and `PATH`.
+    pub fn resolve_command(&mut self, name: &str) -> Option<PathBuf> {
+        if name.contains('/') {
+            return Some(PathBuf::from(name));
+        }
+        let path = self.get_var("PATH").unwrap_or_default();
+        let entry = self.hash.find(name, &path)?;
+        entry.hits += 1;
+        Some(entry.path.clone())
+    }
+
+    /// Command names offered by tab completion: aliases, builtins and
+    /// executables on `PATH`.
+    pub fn completion_candidates(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .aliases
+            .keys()
+            .chain(&self.command_names)
+            .cloned()
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
     pub fn execute_pipelines(&mut self, pipelines: Vec<Pipeline>) {
-        for pipeline in pipelines {
-            match execute_pipeline(self, pipeline) {
-                Ok(_exit_code) => {}
-                Err(e) => match e {
-                    ShellError::CommandNotFound(cmd) => {
-                        eprintln!("{}", cmd);
-                    }
-                    ShellError::InternalError(msg) => {
-                        eprintln!("{}", msg);
-                    }
-                    _ => {
-                        eprintln!("{}", e);
+        let mut pipelines = pipelines.into_iter().peekable();
+        while let Some(pipeline) = pipelines.next() {
+            let skipped = match pipeline.and_or {
+                Some(AndOr::And) => self.last_status != 0,
+                Some(AndOr::Or) => self.last_status == 0,
+                None => false,
+            };
+            if skipped {
+                continue;
+            }
+            // A failure that `&&` or `||` tests, like a negated one, is
+            // handled by the script and neither runs the ERR trap nor ends
+            // the shell under `set -e`.
+            let tested =
+                pipeline.negated || pipelines.peek().is_some_and(|next| next.and_or.is_some());
+
+            self.run_pending_traps();
+            self.run_trap(Condition::Debug);
+            self.last_status = match execute_pipeline(self, pipeline) {
+                Ok(status) => status,
+                Err(e) => {
+                    Self::report_error(&e);
+                    // An unset parameter under `set -u` ends a script.
+                    if matches!(e, ShellError::Unbound(_)) && !self.interactive {
+                        self.exit(e.status());
                     }
-                },
+                    e.status()
+                }
+            };
+            if self.last_status != 0 && !tested {
+                self.run_trap(Condition::Err);
+                if self.errexit && !self.traps.running {
+                    self.exit(self.last_status);
+                }
             }
         }
+        self.run_pending_traps();
+    }
+
+    /// Runs the action trapped for `condition`, if any, keeping `$?`. Traps
+    /// do not run while another trap's action is running.
+    pub fn run_trap(&mut self, condition: Condition) {
+        if self.traps.running {
+            return;
+        }
+        let Some(action) = self.traps.action(condition).map(str::to_string) else {
+            return;
+        };
+        let status = self.last_status;
+        self.traps.running = true;
+        self.run_action(&action);
+        self.traps.running = false;
+        self.last_status = status;
+    }
+
+    /// Runs the traps of the signals caught since the last safe point.
+    fn run_pending_traps(&mut self) {
+        if self.traps.running {
+            return;
+        }
+        for signal in take_pending() {
+            self.run_trap(Condition::Signal(signal));
+        }
+    }
+
+    /// Parses and runs a trap action as a whole, without counting its lines.
+    fn run_action(&mut self, action: &str) {
+        let mut parser = Parser::new();
+        parser.aliases.clone_from(&self.aliases);
+        parser.posix = self.posix;
+        let status = parser.feed(&format!("{}\n", action));
+        self.handle_parse_status(status);
+        let status = parser.finish();
+        self.handle_parse_status(status);
+    }
+
+    /// Runs the EXIT trap, then exits with `status`.
+    pub fn exit(&mut self, status: i32) -> ! {
+        if let Some(action) = self.traps.take(Condition::Exit) {
+            self.last_status = status;
+            self.traps.running = true;
+            self.run_action(&action);
+        }
+        let _ = io::stdout().flush();
+        process::exit(status);
+    }
+
+    /// `$-`: the letters of the short options that are on.
+    pub fn option_flags(&self) -> String {
+        [
+            ('e', self.errexit),
+            ('i', self.interactive),
+            ('u', self.nounset),
+            ('v', self.verbose),
+            ('x', self.xtrace),
+        ]
+        .iter()
+        .filter(|(_, on)| *on)
+        .map(|(letter, _)| letter)
+        .collect()
+    }
+
+    /// The signals the shell ignores for itself: the ones `trap -` leaves
+    /// ignored, and that children restore to their default action.
+    pub fn ignored_signals(&self) -> Vec<i32> {
+        let mut signals = Vec::new();
+        if self.interactive {
+            signals.extend(INTERACTIVE_SIGNALS);
+        }
+        if self.terminal.is_some() {
+            signals.extend(JOB_CONTROL_SIGNALS);
+        }
+        signals
+    }
+
+    /// The signals a child restores to their default action: the ones the
+    /// shell ignores for itself, unless a trap ignores them as well. Like
+    /// every Rust program, the shell also runs with `SIGPIPE` ignored.
+    pub fn restored_signals(&self) -> Vec<i32> {
+        let mut signals = self.ignored_signals();
+        signals.push(libc::SIGPIPE);
+        signals.retain(|&signal| !self.traps.ignores(signal));
+        signals
+    }
+
+    /// Reports the background jobs that have finished since the last prompt.
+    fn notify_jobs(&mut self) {
+        self.jobs.update();
+        for notice in self.jobs.take_finished() {
+            eprintln!("{}", notice);
+        }
+    }
+
+    pub fn report_error(error: &ShellError) {
+        eprintln!("{}", error.message());
     }
 
     #[cfg(unix)]
@@ -94,45 +344,132 @@ impl Shell {
             .unwrap_or(false)
     }
 
-    fn parse_input(&mut self, input: &str) -> Result<Vec<Pipeline>, ShellError> {
-        let tokens = Token::tokenize(input)?;
-        let pipelines = parse_tokens(tokens)?;
-        Ok(pipelines)
+    /// Runs a `-c` string or script without the line editor, a command at a
+    /// time, so aliases defined on one line apply to the lines after it.
+    pub fn run_source(&mut self, source: &str) {
+        let mut parser = Parser::new();
+        for (index, line) in source.split_inclusive('\n').enumerate() {
+            self.line_number = index + 1;
+            if self.verbose {
+                eprint!("{}", line);
+            }
+            if parser.is_idle() {
+                parser.aliases.clone_from(&self.aliases);
+                parser.posix = self.posix;
+            }
+            self.handle_parse_status(parser.feed(line));
+        }
+        self.handle_parse_status(parser.finish());
+    }
+
+    fn handle_parse_status(&mut self, status: ParseStatus) {
+        match status {
+            ParseStatus::Complete(pipelines) => self.execute_pipelines(pipelines),
+            ParseStatus::NeedMore(_) => {}
+            ParseStatus::Error(_, message) => {
+                eprintln!("{}", ShellError::ParseError(message));
+                self.last_status = 2;
+            }
+        }
     }
 
     pub fn run(&mut self) {
+        self.interactive = true;
+        ignore_interactive_signals();
+        self.terminal = Terminal::acquire();
         let mut rl: Editor<MyHelper, DefaultHistory> =
             Editor::with_config(self.config.clone()).unwrap();
 
         let h = MyHelper {
             file_completer: FilenameCompleter::new(),
-            commands: self.command_names.clone(),
+            commands: self.completion_candidates(),
         };
 
         rl.set_helper(Some(h));
 
         rl.bind_sequence(KeyEvent::from('\t'), Cmd::Complete);
 
+        let mut parser = Parser::new();
+        // The lines of the command being read, for the history.
+        let mut command = String::new();
+
         loop {
-            let readline = rl.readline("$ ");
+            if parser.is_idle() {
+                self.run_pending_traps();
+                self.notify_jobs();
+            }
+            let prompt = if parser.is_idle() { "$ " } else { "> " };
+            let readline = rl.readline(prompt);
             match readline {
                 Ok(line) => {
-                    let input = line.trim();
-                    if input.is_empty() {
-                        continue;
+                    self.line_number += 1;
+                    if self.verbose {
+                        eprintln!("{}", line);
                     }
+                    let input = if parser.is_idle() {
+                        let input = line.trim();
+                        if input.is_empty() {
+                            continue;
+                        }
+                        parser.aliases.clone_from(&self.aliases);
+                        parser.posix = self.posix;
+                        input
+                    } else {
+                        line.as_str()
+                    };
 
-                    match self.parse_input(input) {
-                        Ok(pipelines) => {
-                            self.execute_pipelines(pipelines);
+                    let expansion = match expand_history(input, &history_entries(rl.history())) {
+                        Ok(expansion) => expansion,
+                        Err(ShellError::InternalError(msg)) => {
+                            eprintln!("{}", msg);
+                            continue;
                         }
                         Err(e) => {
                             eprintln!("{}", e);
+                            continue;
                         }
+                    };
+                    if expansion.expanded {
+                        println!("{}", expansion.line);
+                    }
+                    if expansion.print_only {
+                        let _ = rl.add_history_entry(expansion.line.as_str());
+                        continue;
+                    }
+
+                    if !command.is_empty() {
+                        command.push('\n');
+                    }
+                    command.push_str(&expansion.line);
+
+                    let status = parser.feed(&format!("{}\n", expansion.line));
+                    if matches!(status, ParseStatus::NeedMore(_)) {
+                        continue;
+                    }
+                    let _ = rl.add_history_entry(command.as_str());
+                    command.clear();
+                    self.handle_parse_status(status);
+
+                    if let Some(helper) = rl.helper_mut() {
+                        helper.commands = self.completion_candidates();
+                    }
+                }
+                Err(ReadlineError::Interrupted) => {
+                    // Ctrl-C drops the line, and any unfinished command.
+                    parser = Parser::new();
+                    command.clear();
+                    self.last_status = 130;
+                }
+                Err(ReadlineError::Eof) => {
+                    if !parser.is_idle() {
+                        self.handle_parse_status(parser.finish());
                     }
+                    eprintln!("exit");
+                    break;
                 }
                 Err(e) => {
                     eprintln!("Shell read error: {}", e);
+                    break;
                 }
             }
         }
@@ -0,0 +1,182 @@
+use std::{
+    os::unix::process::ExitStatusExt,
+    process::ExitStatus,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Signal numbers, names without the `SIG` prefix, and the descriptions the
+/// shell prints when a job is stopped or killed by them.
+pub const SIGNALS: &[(i32, &str, &str)] = &[
+    (libc::SIGHUP, "HUP", "Hangup"),
+    (libc::SIGINT, "INT", "Interrupt"),
+    (libc::SIGQUIT, "QUIT", "Quit"),
+    (libc::SIGILL, "ILL", "Illegal instruction"),
+    (libc::SIGTRAP, "TRAP", "Trace/breakpoint trap"),
+    (libc::SIGABRT, "ABRT", "Aborted"),
+    (libc::SIGBUS, "BUS", "Bus error"),
+    (libc::SIGFPE, "FPE", "Floating point exception"),
+    (libc::SIGKILL, "KILL", "Killed"),
+    (libc::SIGUSR1, "USR1", "User defined signal 1"),
+    (libc::SIGSEGV, "SEGV", "Segmentation fault"),
+    (libc::SIGUSR2, "USR2", "User defined signal 2"),
+    (libc::SIGPIPE, "PIPE", "Broken pipe"),
+    (libc::SIGALRM, "ALRM", "Alarm clock"),
+    (libc::SIGTERM, "TERM", "Terminated"),
+    (libc::SIGCHLD, "CHLD", "Child exited"),
+    (libc::SIGCONT, "CONT", "Continued"),
+    (libc::SIGSTOP, "STOP", "Stopped (signal)"),
+    (libc::SIGTSTP, "TSTP", "Stopped"),
+    (libc::SIGTTIN, "TTIN", "Stopped (tty input)"),
+    (libc::SIGTTOU, "TTOU", "Stopped (tty output)"),
+    (libc::SIGURG, "URG", "Urgent I/O condition"),
+    (libc::SIGXCPU, "XCPU", "CPU time limit exceeded"),
+    (libc::SIGXFSZ, "XFSZ", "File size limit exceeded"),
+    (libc::SIGVTALRM, "VTALRM", "Virtual timer expired"),
+    (libc::SIGPROF, "PROF", "Profiling timer expired"),
+    (libc::SIGWINCH, "WINCH", "Window changed"),
+    (libc::SIGIO, "IO", "I/O possible"),
+    (libc::SIGSYS, "SYS", "Bad system call"),
+];
+
+/// Parses a signal given as a number, a name such as `TERM`, or a name with
+/// the `SIG` prefix, ignoring case.
+pub fn signal_number(spec: &str) -> Option<i32> {
+    if let Ok(number) = spec.parse::<i32>() {
+        return (number == 0 || signal_name(number).is_some()).then_some(number);
+    }
+    let upper = spec.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    SIGNALS
+        .iter()
+        .find(|(_, signal, _)| *signal == name)
+        .map(|(number, _, _)| *number)
+}
+
+pub fn signal_name(number: i32) -> Option<&'static str> {
+    SIGNALS
+        .iter()
+        .find(|(signal, _, _)| *signal == number)
+        .map(|(_, name, _)| *name)
+}
+
+pub fn signal_description(number: i32) -> String {
+    SIGNALS
+        .iter()
+        .find(|(signal, _, _)| *signal == number)
+        .map(|(_, _, description)| description.to_string())
+        .unwrap_or_else(|| format!("Unknown signal {}", number))
+}
+
+/// Converts a status from `waitpid` into the shell's exit status, with a
+/// process killed by signal N giving 128+N.
+pub fn exit_status(status: libc::c_int) -> i32 {
+    if libc::WIFSIGNALED(status) {
+        128 + libc::WTERMSIG(status)
+    } else if libc::WIFSTOPPED(status) {
+        128 + libc::WSTOPSIG(status)
+    } else {
+        libc::WEXITSTATUS(status)
+    }
+}
+
+/// Signals an interactive shell ignores, so that Ctrl-C, Ctrl-\ and Ctrl-Z
+/// only reach the foreground job.
+pub const INTERACTIVE_SIGNALS: &[i32] = &[libc::SIGINT, libc::SIGQUIT, libc::SIGTSTP];
+
+/// Signals a shell with job control also ignores, so that it can take the
+/// terminal back from the background.
+pub const JOB_CONTROL_SIGNALS: &[i32] = &[libc::SIGTTOU, libc::SIGTTIN];
+
+pub fn ignore_interactive_signals() {
+    for &signal in INTERACTIVE_SIGNALS {
+        unsafe { libc::signal(signal, libc::SIG_IGN) };
+    }
+}
+
+/// Restores the default action of the signals the shell ignores for itself,
+/// leaving the ones it inherited ignored alone. Runs in a child before it
+/// execs or runs a command, so it must only make async-signal-safe calls.
+pub fn reset_signals(signals: &[i32]) {
+    for &signal in signals {
+        unsafe { libc::signal(signal, libc::SIG_DFL) };
+    }
+}
+
+/// What the shell prints when a signal ends a foreground process, such as
+/// `Killed` or `Segmentation fault (core dumped)`. Interrupts and broken
+/// pipes are routine and go unreported.
+pub fn termination_message(status: &ExitStatus) -> Option<String> {
+    let signal = status.signal()?;
+    if signal == libc::SIGINT || signal == libc::SIGPIPE {
+        return None;
+    }
+    let core = if status.core_dumped() {
+        " (core dumped)"
+    } else {
+        ""
+    };
+    Some(format!("{}{}", signal_description(signal), core))
+}
+
+/// One more than the highest signal number.
+const NSIG: usize = 65;
+
+/// The signals caught since the shell last ran their traps.
+static PENDING: [AtomicBool; NSIG] = [const { AtomicBool::new(false) }; NSIG];
+
+extern "C" fn record_signal(signal: libc::c_int) {
+    if let Some(pending) = PENDING.get(signal as usize) {
+        pending.store(true, Ordering::Relaxed);
+    }
+}
+
+pub enum Disposition {
+    /// Recorded, for its trap to run at the next safe point.
+    Catch,
+    Ignore,
+    Default,
+}
+
+pub fn set_disposition(signal: i32, disposition: Disposition) {
+    let handler = match disposition {
+        Disposition::Catch => record_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        Disposition::Ignore => libc::SIG_IGN,
+        Disposition::Default => libc::SIG_DFL,
+    };
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler;
+        // Reads and waits carry on after the signal is recorded.
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(signal, &action, std::ptr::null_mut());
+    }
+}
+
+/// Takes the signals caught since the last call, in numeric order.
+pub fn take_pending() -> Vec<i32> {
+    (1..NSIG)
+        .filter(|&signal| PENDING[signal].swap(false, Ordering::Relaxed))
+        .map(|signal| signal as i32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exit_status, termination_message};
+    use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+    #[test]
+    fn test_describes_signal_deaths() {
+        let message = |raw| termination_message(&ExitStatus::from_raw(raw));
+        assert_eq!(message(libc::SIGKILL), Some("Killed".to_string()));
+        assert_eq!(
+            message(libc::SIGSEGV | 0x80),
+            Some("Segmentation fault (core dumped)".to_string())
+        );
+        assert_eq!(message(libc::SIGINT), None);
+        assert_eq!(message(3 << 8), None);
+        assert_eq!(exit_status(libc::SIGKILL), 137);
+        assert_eq!(exit_status(3 << 8), 3);
+    }
+}
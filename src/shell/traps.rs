@@ -0,0 +1,137 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::shell::signals::{Disposition, set_disposition, signal_name, signal_number};
+
+/// What a trap is set on: a signal or one of the shell's own conditions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Condition {
+    /// The shell exiting, through `exit` or at the end of its input.
+    Exit,
+    Signal(i32),
+    /// Before each command.
+    Debug,
+    /// After a command that fails.
+    Err,
+    /// After a function or sourced script returns. The shell has neither, so
+    /// this trap is kept and listed but never runs.
+    Return,
+}
+
+impl Condition {
+    /// Parses a condition the way `trap` accepts it: `EXIT` or 0, `ERR`,
+    /// `DEBUG`, `RETURN`, or a signal name or number.
+    pub fn parse(spec: &str) -> Option<Self> {
+        match spec.to_ascii_uppercase().as_str() {
+            "EXIT" | "SIGEXIT" | "0" => Some(Condition::Exit),
+            "ERR" => Some(Condition::Err),
+            "DEBUG" => Some(Condition::Debug),
+            "RETURN" => Some(Condition::Return),
+            _ => signal_number(spec)
+                .filter(|&signal| signal != 0)
+                .map(Condition::Signal),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Exit => write!(f, "EXIT"),
+            Condition::Signal(signal) => write!(f, "SIG{}", signal_name(*signal).unwrap_or("?")),
+            Condition::Debug => write!(f, "DEBUG"),
+            Condition::Err => write!(f, "ERR"),
+            Condition::Return => write!(f, "RETURN"),
+        }
+    }
+}
+
+/// The actions set with `trap`. An empty action ignores the condition.
+#[derive(Debug, Default)]
+pub struct Traps {
+    actions: BTreeMap<Condition, String>,
+    /// Whether a trap action is running, during which no other trap runs.
+    pub running: bool,
+}
+
+impl Traps {
+    pub fn actions(&self) -> impl Iterator<Item = (&Condition, &String)> {
+        self.actions.iter()
+    }
+
+    pub fn action(&self, condition: Condition) -> Option<&str> {
+        self.actions.get(&condition).map(String::as_str)
+    }
+
+    /// Whether `trap ''` has set the signal to be ignored.
+    pub fn ignores(&self, signal: i32) -> bool {
+        self.action(Condition::Signal(signal)) == Some("")
+    }
+
+    /// Sets the action for a condition, catching or ignoring the signal.
+    pub fn set(&mut self, condition: Condition, action: String) {
+        if let Condition::Signal(signal) = condition {
+            let disposition = if action.is_empty() {
+                Disposition::Ignore
+            } else {
+                Disposition::Catch
+            };
+            set_disposition(signal, disposition);
+        }
+        self.actions.insert(condition, action);
+    }
+
+    /// Removes the action for a condition. A signal goes back to what the
+    /// shell does without a trap, which for an interactive shell may be to
+    /// ignore it.
+    pub fn reset(&mut self, condition: Condition, ignored: &[i32]) {
+        if let Condition::Signal(signal) = condition {
+            let disposition = if ignored.contains(&signal) {
+                Disposition::Ignore
+            } else {
+                Disposition::Default
+            };
+            set_disposition(signal, disposition);
+        }
+        self.actions.remove(&condition);
+    }
+
+    /// Takes the action for a condition, so that it runs at most once.
+    pub fn take(&mut self, condition: Condition) -> Option<String> {
+        self.actions.remove(&condition)
+    }
+
+    /// Clears the traps in a subshell, which only keeps ignored signals.
+    pub fn reset_for_subshell(&mut self) {
+        self.running = false;
+        self.actions.retain(|condition, action| {
+            if let Condition::Signal(signal) = condition
+                && !action.is_empty()
+            {
+                set_disposition(*signal, Disposition::Default);
+            }
+            action.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Condition;
+
+    #[test]
+    fn test_parses_conditions() {
+        assert_eq!(Condition::parse("EXIT"), Some(Condition::Exit));
+        assert_eq!(Condition::parse("0"), Some(Condition::Exit));
+        assert_eq!(Condition::parse("err"), Some(Condition::Err));
+        assert_eq!(
+            Condition::parse("SIGINT"),
+            Some(Condition::Signal(libc::SIGINT))
+        );
+        assert_eq!(
+            Condition::parse("15"),
+            Some(Condition::Signal(libc::SIGTERM))
+        );
+        assert_eq!(Condition::parse("BOGUS"), None);
+        assert_eq!(Condition::Signal(libc::SIGTERM).to_string(), "SIGTERM");
+    }
+}